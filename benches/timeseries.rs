@@ -0,0 +1,76 @@
+//! Compares `TimeSeries` lookups against the linear scan it used to do for every entity every
+//! frame, on a history the size of a long run.
+
+use bevy::ecs::component::Component;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/timeseries.rs"]
+mod timeseries;
+
+use timeseries::{Interpolate, TimeSeries};
+
+const SAMPLES: usize = 50_000;
+const STEP: f64 = 0.01;
+
+#[derive(Clone, Component)]
+struct Value;
+
+impl Interpolate for Value {}
+
+/// The original lookup, scanning the whole history for the last sample within 0.01s
+#[allow(clippy::double_ended_iterator_last)]
+fn linear_at_or_before(history: &[(f64, Value)], time: f64) -> Option<&Value> {
+    history
+        .iter()
+        .filter_map(move |(t, v)| {
+            if *t <= time && (*t - time) > -0.01 {
+                Some(v)
+            } else {
+                None
+            }
+        })
+        .last()
+}
+
+fn history() -> Vec<(f64, Value)> {
+    (0..SAMPLES).map(|i| (i as f64 * STEP, Value)).collect()
+}
+
+fn lookup(c: &mut Criterion) {
+    let history = history();
+    let series = TimeSeries::new(history.clone());
+    let end = SAMPLES as f64 * STEP;
+
+    let mut group = c.benchmark_group("at_or_before");
+    group.bench_function("linear", |b| {
+        let mut time = 0.0;
+        b.iter(|| {
+            time = (time + STEP) % end;
+            black_box(linear_at_or_before(&history, black_box(time)))
+        })
+    });
+    group.bench_function("playback", |b| {
+        let mut time = 0.0;
+        b.iter(|| {
+            time = (time + STEP) % end;
+            black_box(series.at_or_before(black_box(time)))
+        })
+    });
+    group.bench_function("scrubbing", |b| {
+        // Jump around the run so the cached cursor never helps
+        let mut time = 0.0;
+        b.iter(|| {
+            time = (time + end * 0.377) % end;
+            black_box(series.at_or_before(black_box(time)))
+        })
+    });
+    group.finish();
+
+    c.bench_function("range", |b| {
+        b.iter(|| black_box(series.range(black_box(100.0), black_box(110.0)).len()))
+    });
+}
+
+criterion_group!(benches, lookup);
+criterion_main!(benches);
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use bevy::ecs::component::Component;
+use bevy::ecs::query::With;
+use bevy::ecs::system::{Query, Resource};
+use bevy::math::Vec3;
+use bevy::render::color::Color;
+use bevy::text::Text;
+
+use crate::camera::Views;
+use crate::covariance::Covariance;
+use crate::data::Step;
+use crate::frame::Frame;
+use crate::metrics::assignment;
+use crate::state::State;
+use crate::timeseries::{Active, Interpolate};
+use crate::track::Track;
+
+/// Parameters of track to truth association
+#[derive(clap::Args, Resource, Clone, Copy, Debug)]
+pub struct AssociationConfig {
+    /// Maximum distance between a track and a truth for them to be associated, in meters
+    #[arg(long, default_value_t = 10_000.0)]
+    pub gate: f32,
+}
+
+/// The truth a track is associated with at one instant, and the resulting track errors
+#[derive(Clone, Debug, Default, Component)]
+pub struct Association {
+    pub truth: Option<String>,
+    pub truth_pos: Vec3,
+    pub position_error: f32,
+    pub velocity_error: f32,
+    /// Normalized estimation error squared of the position
+    pub nees: f32,
+    /// Fraction of the track's life so far spent on its most common truth
+    pub purity: f32,
+}
+
+/// Associations are held until the next step, since they are only known at each step
+impl Interpolate for Association {}
+
+/// Assign tracks to truths by global nearest neighbor, rejecting pairs further apart than `gate`.
+/// Returns the truth assigned to each track.
+pub fn associate<'a>(
+    truths: &HashMap<&'a str, State>,
+    tracks: &HashMap<&'a str, State>,
+    gate: f32,
+) -> HashMap<&'a str, &'a str> {
+    let mut truth_ids: Vec<&str> = truths.keys().copied().collect();
+    truth_ids.sort_unstable();
+    let mut track_ids: Vec<&str> = tracks.keys().copied().collect();
+    track_ids.sort_unstable();
+    let distance =
+        |track_id: &str, truth_id: &str| tracks[track_id].pos.distance(truths[truth_id].pos);
+
+    // Each track can also be left unassigned, in one of the columns after the truths, for the
+    // cost of the gate. A pair further apart than the gate is then never worth more than that.
+    let cost: Vec<Vec<f64>> = track_ids
+        .iter()
+        .map(|track_id| {
+            truth_ids
+                .iter()
+                .map(|truth_id| distance(track_id, truth_id).min(gate) as f64)
+                .chain(track_ids.iter().map(|_| gate as f64))
+                .collect()
+        })
+        .collect();
+
+    track_ids
+        .iter()
+        .zip(assignment(&cost))
+        .filter_map(|(track_id, column)| {
+            let truth_id = *truth_ids.get(column)?;
+            (distance(track_id, truth_id) <= gate).then_some((*track_id, truth_id))
+        })
+        .collect()
+}
+
+/// Associates tracks to truths one step at a time, remembering each track's past assignments
+#[derive(Default)]
+pub struct Associator {
+    /// Number of steps each track has existed, and how many of those it spent on each truth
+    counts: HashMap<String, (usize, HashMap<String, usize>)>,
+}
+
+impl Associator {
+    pub fn step(
+        &mut self,
+        step: &Step,
+        frame: &Frame,
+        config: &AssociationConfig,
+    ) -> Vec<(String, Association)> {
+        let truths: HashMap<&str, State> = step
+            .truths
+            .iter()
+            .map(|(id, truth)| (id.as_str(), frame.state(truth)))
+            .collect();
+        let tracks: HashMap<&str, State> = step
+            .tracks
+            .iter()
+            .map(|(id, track)| (id.as_str(), track.state(frame)))
+            .collect();
+        let assigned = associate(&truths, &tracks, config.gate);
+
+        let mut associations = Vec::with_capacity(tracks.len());
+        for (track_id, track) in tracks.iter() {
+            let (steps, counts) = self.counts.entry(track_id.to_string()).or_default();
+            *steps += 1;
+
+            let mut association = Association::default();
+            if let Some(truth_id) = assigned.get(track_id) {
+                *counts.entry(truth_id.to_string()).or_default() += 1;
+
+                let truth = &truths[truth_id];
+                let error = track.pos - truth.pos;
+                association.truth = Some(truth_id.to_string());
+                association.truth_pos = truth.pos;
+                association.position_error = error.length();
+                association.velocity_error = track.vel.distance(truth.vel);
+                association.nees = nees(error, &step.tracks[*track_id].covariance(frame));
+            }
+            let best = counts.values().max().copied().unwrap_or_default();
+            association.purity = best as f32 / *steps as f32;
+
+            associations.push((track_id.to_string(), association));
+        }
+        associations
+    }
+}
+
+/// Associate every step of a run, returning the association history of each track
+pub fn associate_run(
+    steps: impl Iterator<Item = Step>,
+    frame: &Frame,
+    config: &AssociationConfig,
+) -> HashMap<String, Vec<(f64, Association)>> {
+    let mut associator = Associator::default();
+    let mut histories: HashMap<String, Vec<(f64, Association)>> = HashMap::new();
+    for step in steps {
+        for (track_id, association) in associator.step(&step, frame, config) {
+            histories
+                .entry(track_id)
+                .or_default()
+                .push((step.elapsed, association));
+        }
+    }
+    histories
+}
+
+/// Normalized estimation error squared, NaN if the covariance is singular
+fn nees(error: Vec3, covariance: &Covariance) -> f32 {
+    if covariance.0.determinant().abs() <= f32::EPSILON {
+        return f32::NAN;
+    }
+    error.dot(covariance.0.inverse() * error)
+}
+
+/// Draw a line from each track to the truth it is associated with
+pub fn render_associations(
+    mut views: Views,
+    track_query: Query<(&State, &Association, &Active), With<Track>>,
+) {
+    let color = Color::GREEN;
+    for (view, gizmos) in views.iter_mut() {
+        for (state, association, active) in track_query.iter() {
+            if !active.0 || association.truth.is_none() {
+                continue;
+            }
+            let (track, truth) = (state.pos, association.truth_pos);
+            gizmos.line(view.project(track), view.project(truth), color);
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct AssociationText;
+pub fn association_text_update(
+    track_query: Query<(&Track, &Association, &Active)>,
+    mut query: Query<&mut Text, With<AssociationText>>,
+) {
+    let mut tracks: Vec<_> = track_query
+        .iter()
+        .filter(|(_, _, active)| active.0)
+        .collect();
+    tracks.sort_by(|a, b| a.0 .0.cmp(&b.0 .0));
+
+    let mut value = String::from("Track  Truth  Pos err  Vel err  NEES  Purity\n");
+    for (track, association, _) in tracks {
+        value += &format!(
+            "{}  {}  {:.1}m  {:.1}m/s  {:.2}  {:.0}%\n",
+            track.0,
+            association.truth.as_deref().unwrap_or("-"),
+            association.position_error,
+            association.velocity_error,
+            association.nees,
+            association.purity * 100.0,
+        );
+    }
+
+    for mut text in &mut query {
+        text.sections[0].value = value.clone();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use bevy::math::Vec3;
+
+    use crate::state::State;
+
+    use super::associate;
+
+    fn at(x: f32) -> State {
+        State {
+            pos: Vec3::new(x, 0.0, 0.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn gated_nearest_neighbor() {
+        let truths = HashMap::from([("a", at(0.0)), ("b", at(100.0)), ("c", at(5000.0))]);
+        let tracks = HashMap::from([("1", at(10.0)), ("2", at(20.0)), ("3", at(-2000.0))]);
+
+        let assigned = associate(&truths, &tracks, 1000.0);
+        assert_eq!(assigned.get("1"), Some(&"a"));
+        assert_eq!(assigned.get("2"), Some(&"b"));
+        assert_eq!(assigned.get("3"), None);
+
+        // Taking the closest pair first would leave track 2 without a truth
+        let truths = HashMap::from([("a", at(0.0)), ("b", at(200.0))]);
+        let tracks = HashMap::from([("1", at(90.0)), ("2", at(-100.0))]);
+        let assigned = associate(&truths, &tracks, 250.0);
+        assert_eq!(assigned.get("1"), Some(&"b"));
+        assert_eq!(assigned.get("2"), Some(&"a"));
+    }
+}
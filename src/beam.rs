@@ -1,62 +1,233 @@
+use std::f32::consts::TAU;
+use std::fmt;
+
 use bevy::ecs::bundle::Bundle;
 use bevy::ecs::component::Component;
+use bevy::ecs::query::With;
 use bevy::ecs::system::{Query, Res};
-use bevy::gizmos::gizmos::Gizmos;
-use bevy::math::{Quat, Vec3};
+use bevy::math::{Quat, Vec2, Vec3};
 use bevy::render::color::Color;
+use bevy::text::{Text, TextSection, TextStyle};
+use bevy::utils::default;
 
-use crate::polar::PolarVec3;
-use crate::timeseries::{Active, TimeSeries};
+use crate::camera::Views;
+use crate::polar::{pointing, PolarVec3};
+use crate::sensor::Sensors;
+use crate::timeseries::{Active, Interpolate, TimeSeries};
 use crate::RenderMode;
 
-#[derive(Debug, Clone, Component)]
+/// Number of points around the edge of a drawn beam footprint
+const FOOTPRINT_POINTS: usize = 32;
+
+/// Identifies a beam across steps, by its id or, without one, by its position in the step.
+/// Ids and positions are kept apart, so the beam with id 1 is not the second beam without one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BeamKey {
+    Id(u32),
+    Position(usize),
+}
+
+impl Default for BeamKey {
+    fn default() -> Self {
+        Self::Position(0)
+    }
+}
+
+impl fmt::Display for BeamKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "{}", id),
+            Self::Position(position) => write!(f, "#{}", position),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Component)]
 pub struct BeamState {
-    pub width: f32,
+    /// Full azimuth and elevation widths, measured across the beam rather than in scene angles
+    pub width: Vec2,
+    /// Pointing direction relative to the sensor's boresight, at the end of the range gate
     pub target: PolarVec3,
-    pub index: usize,
+    /// Start of the range gate
+    pub min_range: f32,
+    pub key: BeamKey,
+    pub waveform: Option<String>,
+    /// Length of the dwell, in seconds
+    pub dwell: Option<f32>,
+    /// Index of the sensor the beam is from
+    pub sensor: usize,
+    /// Position of the sensor in the scene
+    pub origin: Vec3,
+    /// Rotation from the scene's boresight to the sensor's
+    pub orientation: Quat,
 }
 
-pub fn render_beams(
-    mode: Res<RenderMode>,
-    beam_query: Query<(&BeamState, &Active)>,
-    mut gizmos: Gizmos,
-) {
-    const COLORS: [Color; 4] = [Color::RED, Color::GREEN, Color::BLUE, Color::ORANGE];
+impl BeamState {
+    /// Rotation from the scene's boresight to this beam
+    fn rotation(&self) -> Quat {
+        self.orientation * pointing(self.target.azimuth, self.target.elevation)
+    }
 
-    for (beam, active) in beam_query.iter() {
-        if !active.0 {
-            continue;
+    /// Whether a point in the scene is within the beam
+    pub fn illuminates(&self, pos: Vec3) -> bool {
+        let local = self.rotation().inverse() * (pos - self.origin);
+        let range = local.length();
+        if local.z <= 0.0 || range < self.min_range || range > self.target.range {
+            return false;
         }
+        let offset = PolarVec3::from(local);
+        let offset = Vec2::new(offset.azimuth, offset.elevation) / (self.width / 2.0);
+        offset.length_squared() <= 1.0
+    }
+
+    /// Point on the center of the beam at the given range, in the scene
+    pub fn center(&self, range: f32) -> Vec3 {
+        self.origin + self.rotation() * Vec3::Z * range
+    }
+
+    /// Points around the edge of the beam at the given range, in the scene
+    pub fn footprint(&self, range: f32) -> Vec<Vec3> {
+        let rotation = self.rotation();
+        (0..=FOOTPRINT_POINTS)
+            .map(|i| {
+                let angle = i as f32 / FOOTPRINT_POINTS as f32 * TAU;
+                let offset = Vec2::new(angle.cos(), angle.sin()) * self.width / 2.0;
+                self.origin + rotation * Vec3::from(PolarVec3::new(range, offset.x, offset.y))
+            })
+            .collect()
+    }
+}
+
+/// Beams jump between dwells rather than sweeping, so each sample is held until the next
+impl Interpolate for BeamState {}
 
-        let color = COLORS[beam.index];
-        match mode.as_ref() {
-            RenderMode::Spherical => {
-                //gizmos.circle(beam.target.direct_vec3(), Vec3::NEG_X, beam.width / 2.0, color);
-                gizmos.sphere(
-                    beam.target.direct_vec3(),
-                    Quat::default(),
-                    beam.width / 2.0,
-                    color,
-                );
+/// Color of a beam. Hues are spread by the golden angle, so any number of beams stay distinct
+/// and each beam keeps its color however many others there are.
+pub fn beam_color(key: BeamKey) -> Color {
+    // Ids and positions take turns, so neither shares the other's colors
+    let index = match key {
+        BeamKey::Id(id) => id as usize * 2,
+        BeamKey::Position(position) => position * 2 + 1,
+    };
+    Color::hsl((index as f32 * 137.508) % 360.0, 0.9, 0.45)
+}
+
+/// Color of a waveform, the same for every beam and every run
+pub fn waveform_color(waveform: &str) -> Color {
+    // FNV-1a, since the std hasher is not stable between builds
+    let hash = waveform.bytes().fold(0x811c9dc5u32, |h, b| {
+        (h ^ b as u32).wrapping_mul(0x01000193)
+    });
+    Color::hsl(hash as f32 % 360.0, 0.7, 0.35)
+}
+
+impl BeamState {
+    /// Beams with a waveform are colored by it, so search and track dwells can be told apart
+    pub fn color(&self) -> Color {
+        match &self.waveform {
+            Some(waveform) => waveform_color(waveform),
+            None => beam_color(self.key),
+        }
+    }
+}
+
+pub fn render_beams(mut views: Views, beam_query: Query<(&BeamState, &Active)>) {
+    for (view, gizmos) in views.iter_mut() {
+        for (beam, active) in beam_query.iter() {
+            if !active.0 {
+                continue;
             }
-            RenderMode::Cartesian => {
-                // Cartesian beams are drawn as a circle at the target range, and a line back to
-                // the origin from each corner to make a cone.
-                let beam_center = beam.target.clone().into();
-                let beam_radius = beam.target.range * (beam.width / 2.0).sin();
-                gizmos.circle(beam_center, -Vec3::Z, beam_radius, color);
-                gizmos.line(Vec3::default(), beam_center + beam_radius * Vec3::X, color);
-                gizmos.line(Vec3::default(), beam_center - beam_radius * Vec3::X, color);
-                gizmos.line(Vec3::default(), beam_center + beam_radius * Vec3::Y, color);
-                gizmos.line(Vec3::default(), beam_center - beam_radius * Vec3::Y, color);
+
+            let color = beam.color();
+            let far = beam.footprint(beam.target.range);
+            // Beams without a range gate start at the sensor
+            let near = match beam.min_range {
+                range if range > 0.0 => beam.footprint(range),
+                _ => vec![beam.origin; far.len()],
+            };
+            match view.mode {
+                RenderMode::Spherical | RenderMode::BScope => {
+                    // The footprint in scene angles, which stretches as the beam nears the poles.
+                    // The range gate is drawn along the beam's center.
+                    let project = |p: &Vec3| view.project(*p);
+                    gizmos.linestrip(far.iter().map(project), color);
+                    if beam.min_range > 0.0 {
+                        gizmos.linestrip(near.iter().map(project), color);
+                        let center = |range| project(&beam.center(range));
+                        gizmos.line(center(beam.min_range), center(beam.target.range), color);
+                    }
+                }
+                _ => {
+                    // Otherwise beams are drawn as their footprint at each end of the range gate,
+                    // joined along each edge to make a cone or frustum.
+                    let near: Vec<_> = near.into_iter().map(|p| view.project(p)).collect();
+                    let far: Vec<_> = far.into_iter().map(|p| view.project(p)).collect();
+                    for i in 0..4 {
+                        let i = i * FOOTPRINT_POINTS / 4;
+                        gizmos.line(near[i], far[i], color);
+                    }
+                    if beam.min_range > 0.0 {
+                        gizmos.linestrip(near, color);
+                    }
+                    gizmos.linestrip(far, color);
+                }
             }
         }
     }
 }
 
+#[derive(Component)]
+pub struct BeamText;
+pub fn beam_text_update(
+    sensors: Res<Sensors>,
+    beam_query: Query<(&BeamState, &Active)>,
+    mut query: Query<&mut Text, With<BeamText>>,
+) {
+    let mut beams: Vec<_> = beam_query.iter().filter(|(_, active)| active.0).collect();
+    beams.sort_by_key(|(beam, _)| beam.key);
+
+    let style = |color| TextStyle { color, ..default() };
+    let mut sections = vec![TextSection::new(
+        "Beam  Sensor  Waveform  Gate  Dwell\n",
+        style(Color::BLACK),
+    )];
+    for (beam, _) in beams {
+        let dwell = match beam.dwell {
+            Some(dwell) => format!("{:.1}ms", dwell * 1000.0),
+            None => "-".to_string(),
+        };
+        let sensor = sensors.0.get(beam.sensor).map_or("-", |s| s.name.as_str());
+        let value = format!(
+            "{}  {}  {}  {:.0}-{:.0}km  {}\n",
+            beam.key,
+            sensor,
+            beam.waveform.as_deref().unwrap_or("-"),
+            beam.min_range / 1000.0,
+            beam.target.range / 1000.0,
+            dwell,
+        );
+        sections.push(TextSection::new(value, style(beam.color())));
+    }
+
+    for mut text in &mut query {
+        text.sections = sections.clone();
+    }
+}
+
 #[derive(Bundle)]
 pub struct BeamBundle {
     pub state: BeamState,
     pub history: TimeSeries<BeamState>,
     pub active: Active,
 }
+
+impl BeamBundle {
+    /// A beam starting at its first sample, if it has any
+    pub fn new(history: Vec<(f64, BeamState)>) -> Option<Self> {
+        Some(Self {
+            state: history.first()?.1.clone(),
+            active: Active(true),
+            history: TimeSeries::new(history),
+        })
+    }
+}
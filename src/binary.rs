@@ -0,0 +1,256 @@
+//! Compact columnar run format. Every entity's history is stored as contiguous arrays, so a run
+//! loads without parsing and without a map of entities per step.
+//!
+//! All values are little endian:
+//! - magic, then a version byte
+//! - the JSON header, as a `u32` byte length followed by UTF-8
+//! - `u64` step count, then `f64` elapsed time of each step
+//! - `u32` truth count, then for each truth its id, `u64` sample count, `u32` step index of
+//!   each sample and the `f32` state vector of each sample
+//! - `u32` track count, then for each track the same as a truth followed by the `u32` length of
+//!   each sample's uncertainty and all of the `f32` uncertainties
+//! - `u32` beam count of each step, then for every beam its `u32` id (`u32::MAX` for none), its
+//!   `f32` width, its `f32` position pair, its `f32` azimuth and elevation widths, minimum and
+//!   maximum range and dwell (NaN for none), its `u32` sensor index and a `u8` that is 1 if it
+//!   is steered
+//! - `u32` waveform count and each waveform, then the `u32` index of every beam's waveform
+//!   (`u32::MAX` for none)
+//!
+//! Ids are a `u32` byte length followed by UTF-8.
+
+use std::io::{BufRead, Write};
+
+use anyhow::{ensure, Result};
+
+use crate::data::{Column, SimulationRun};
+use crate::mapped::MappedRun;
+
+pub const MAGIC: &[u8; 7] = b"RADARV\x00";
+pub const VERSION: u8 = 1;
+
+/// Stored in place of the id of a beam without one
+pub const NO_ID: u32 = u32::MAX;
+
+/// Stored in place of an optional value that is not given
+const NONE: f32 = f32::NAN;
+
+pub fn optional(value: f32) -> Option<f32> {
+    (!value.is_nan()).then_some(value)
+}
+
+pub fn optional_id(id: u32) -> Option<u32> {
+    (id != NO_ID).then_some(id)
+}
+
+/// Check whether a run is in the binary format, without consuming any of it
+pub fn is_binary(reader: &mut impl BufRead) -> Result<bool> {
+    Ok(reader.fill_buf()?.starts_with(MAGIC))
+}
+
+pub fn check_version(version: u8) -> Result<()> {
+    ensure!(
+        version == VERSION,
+        "binary run version {} is not supported, convert it again",
+        version
+    );
+    Ok(())
+}
+
+impl SimulationRun {
+    pub fn write_binary(&self, mut writer: impl Write) -> Result<()> {
+        let w = &mut writer;
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        write_str(w, &serde_json::to_string(&self.header)?)?;
+
+        write_len(w, self.elapsed.len() as u64)?;
+        write_f64s(w, &self.elapsed)?;
+
+        write_u32s(w, &[self.truths.len() as u32])?;
+        for (id, column) in self.truths.iter() {
+            write_column_header(w, id, column)?;
+            write_f32s(w, column.values.iter().flatten())?;
+        }
+
+        write_u32s(w, &[self.tracks.len() as u32])?;
+        for (id, column) in self.tracks.iter() {
+            write_column_header(w, id, column)?;
+            write_f32s(w, column.values.iter().flat_map(|t| t.state.iter()))?;
+            let lengths: Vec<u32> = column
+                .values
+                .iter()
+                .map(|t| t.uncertainty.len() as u32)
+                .collect();
+            write_u32s(w, &lengths)?;
+            write_f32s(w, column.values.iter().flat_map(|t| t.uncertainty.iter()))?;
+        }
+
+        let counts: Vec<u32> = self.beams.iter().map(|b| b.len() as u32).collect();
+        write_u32s(w, &counts)?;
+        let beams = || self.beams.iter().flatten();
+        let ids: Vec<u32> = beams().map(|b| b.id.unwrap_or(NO_ID)).collect();
+        write_u32s(w, &ids)?;
+        write_f32s(w, beams().map(|b| &b.width))?;
+        write_f32s(w, beams().flat_map(|b| b.position.iter()))?;
+        write_f32s(w, beams().map(|b| b.az_width.as_ref().unwrap_or(&NONE)))?;
+        write_f32s(w, beams().map(|b| b.el_width.as_ref().unwrap_or(&NONE)))?;
+        write_f32s(w, beams().map(|b| b.min_range.as_ref().unwrap_or(&NONE)))?;
+        write_f32s(w, beams().map(|b| b.max_range.as_ref().unwrap_or(&NONE)))?;
+        write_f32s(w, beams().map(|b| b.dwell.as_ref().unwrap_or(&NONE)))?;
+        let sensors: Vec<u32> = beams().map(|b| b.sensor).collect();
+        write_u32s(w, &sensors)?;
+        let steered: Vec<u8> = beams().map(|b| b.steered as u8).collect();
+        w.write_all(&steered)?;
+
+        let mut waveforms: Vec<&str> = beams().filter_map(|b| b.waveform.as_deref()).collect();
+        waveforms.sort();
+        waveforms.dedup();
+        write_u32s(w, &[waveforms.len() as u32])?;
+        for waveform in waveforms.iter() {
+            write_str(w, waveform)?;
+        }
+        let indices: Vec<u32> = beams()
+            .map(|b| match &b.waveform {
+                Some(waveform) => waveforms.binary_search(&waveform.as_str()).unwrap() as u32,
+                None => NO_ID,
+            })
+            .collect();
+        write_u32s(w, &indices)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a whole run from its bytes, usually a mapped file. Every count is checked against
+    /// the bytes left before anything is allocated for it.
+    pub fn read_binary(bytes: &[u8]) -> Result<Self> {
+        let run = MappedRun::index(bytes)?;
+        Ok(run.read(0..run.step_count()))
+    }
+}
+
+fn write_column_header<T>(w: &mut impl Write, id: &str, column: &Column<T>) -> Result<()> {
+    write_str(w, id)?;
+    write_len(w, column.steps.len() as u64)?;
+    write_u32s(w, &column.steps)
+}
+
+fn write_str(w: &mut impl Write, s: &str) -> Result<()> {
+    write_u32s(w, &[s.len() as u32])?;
+    Ok(w.write_all(s.as_bytes())?)
+}
+
+fn write_len(w: &mut impl Write, len: u64) -> Result<()> {
+    Ok(w.write_all(&len.to_le_bytes())?)
+}
+
+fn write_u32s(w: &mut impl Write, values: &[u32]) -> Result<()> {
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    Ok(w.write_all(&bytes)?)
+}
+
+fn write_f32s<'a>(w: &mut impl Write, values: impl Iterator<Item = &'a f32>) -> Result<()> {
+    let bytes: Vec<u8> = values.flat_map(|v| v.to_le_bytes()).collect();
+    Ok(w.write_all(&bytes)?)
+}
+
+fn write_f64s(w: &mut impl Write, values: &[f64]) -> Result<()> {
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    Ok(w.write_all(&bytes)?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::data::{SimulationRun, Step};
+    use crate::frame::{Axes, StateOrder};
+
+    use super::{MAGIC, VERSION};
+
+    const RUN: &str = concat!(
+        r#"{"elapsed": 0.0, "truths": {"a": [1, 2, 3, 4, 5, 6]}, "tracks": {}, "#,
+        r#""beams": [{"width": 0.1, "position": [0.2, 0.3], "waveform": "search"}]}"#,
+        "\n",
+        r#"{"elapsed": 0.5, "truths": {"a": [2, 2, 3, 4, 5, 6], "b": [0, 0, 0, 0, 0, 0]}, "#,
+        r#""tracks": {"7": {"state": [1, 1, 1, 1, 1, 1], "uncertainty": [1, 0, 0, 1]}}, "#,
+        r#""beams": []}"#,
+        "\n",
+        r#"{"elapsed": 1.0, "truths": {"b": [9, 0, 0, 0, 0, 0]}, "#,
+        r#""tracks": {"7": {"state": [2, 1, 1, 1, 1, 1], "uncertainty": []}, "#,
+        r#""8": {"state": [3, 1, 1, 1, 1, 1], "uncertainty": [5]}}, "#,
+        r#""beams": [{"width": 0.1, "position": [0.2, 0.3]}, {"id": 4, "width": 0.4, "position": [0.5, 0.6], "el_width": 0.2, "steered": true, "min_range": 100, "max_range": 900, "waveform": "track", "dwell": 0.002}]}"#,
+        "\n",
+    );
+
+    #[test]
+    fn round_trip() {
+        let run = SimulationRun::from_jsonl(Cursor::new(RUN), false).unwrap();
+
+        // The columns rebuild exactly the steps that were read
+        let steps: Vec<Step> = RUN
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(run.steps().collect::<Vec<_>>(), steps);
+
+        let mut binary = Vec::new();
+        run.write_binary(&mut binary).unwrap();
+        let read = SimulationRun::read_binary(&binary).unwrap();
+        assert_eq!(read, run);
+        assert_eq!(read.steps().collect::<Vec<_>>(), steps);
+
+        assert!(SimulationRun::read_binary(&binary[..binary.len() - 1]).is_err());
+
+        // The last beam's waveform is the last value, and there are only two waveforms
+        let mut missing = binary.clone();
+        let end = missing.len();
+        missing[end - 4..].copy_from_slice(&2u32.to_le_bytes());
+        assert!(SimulationRun::read_binary(&missing).is_err());
+    }
+
+    #[test]
+    fn header() {
+        let header = r#"{"frame": {"axes": "ned", "order": "grouped"}}"#;
+        let run =
+            SimulationRun::from_jsonl(Cursor::new(format!("{}\n{}", header, RUN)), false).unwrap();
+        assert_eq!(run.header.frame.axes, Axes::Ned);
+        assert_eq!(run.header.frame.order, StateOrder::Grouped);
+
+        let mut binary = Vec::new();
+        run.write_binary(&mut binary).unwrap();
+        assert_eq!(SimulationRun::read_binary(&binary).unwrap(), run);
+
+        // A header anywhere but the first line is rejected
+        assert!(
+            SimulationRun::from_jsonl(Cursor::new(format!("{}{}", RUN, header)), false).is_err()
+        );
+    }
+
+    #[test]
+    fn corrupt_columns() {
+        // A run of two steps with a single truth, whose sample count and steps are given
+        let run = |samples: u64, steps: &[u32]| {
+            let mut bytes = MAGIC.to_vec();
+            bytes.push(VERSION);
+            bytes.extend(2u32.to_le_bytes());
+            bytes.extend(b"{}");
+            bytes.extend(2u64.to_le_bytes());
+            bytes.extend([0.0f64, 1.0].iter().flat_map(|e| e.to_le_bytes()));
+            bytes.extend(1u32.to_le_bytes());
+            bytes.extend(1u32.to_le_bytes());
+            bytes.extend(b"a");
+            bytes.extend(samples.to_le_bytes());
+            bytes.extend(steps.iter().flat_map(|s| s.to_le_bytes()));
+            bytes.extend([0; 6 * 4 * 2]);
+            SimulationRun::read_binary(&bytes)
+        };
+
+        assert!(run(0, &[]).is_err());
+        assert!(run(2, &[1, 0]).is_err());
+        assert!(run(2, &[0, 0]).is_err());
+        // Counts larger than the run are rejected before anything is allocated for them
+        assert!(run(u64::MAX, &[0, 1]).is_err());
+        assert!(run(u64::MAX / 2, &[0, 1]).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+
+use bevy::ecs::component::Component;
+use bevy::ecs::entity::Entity;
+use bevy::ecs::query::With;
+use bevy::ecs::system::{Local, Query, Res, ResMut, Resource, SystemParam};
+use bevy::math::{UVec2, Vec3};
+use bevy::render::camera::{Camera, Viewport};
+use bevy::transform::components::GlobalTransform;
+use bevy::window::{PrimaryWindow, Window};
+use bevy_panorbit_camera::PanOrbitCamera;
+
+use crate::lines::{Lines, ViewLines};
+use crate::polar::{pointing, PolarVec3};
+use crate::sensor::{Sensor, Sensors};
+use crate::RenderMode;
+
+/// A camera looking at the scene, with the index of its place in the split screen layout
+#[derive(Component, Debug)]
+pub struct SceneCamera(pub usize);
+
+/// Whether the window is split between two scene cameras, side by side
+#[derive(Resource, Debug, Default)]
+pub struct SplitScreen(pub bool);
+
+/// The vertical slice of the scene an RHI view shows
+#[derive(clap::Args, Resource, Clone, Copy, Debug, PartialEq)]
+pub struct RhiCut {
+    /// Azimuth of the slice shown by the RHI view in degrees, turned with [ and ]
+    #[arg(
+        long = "rhi-azimuth",
+        default_value_t = 0.0,
+        allow_negative_numbers = true
+    )]
+    pub azimuth: f32,
+
+    /// Total azimuth extent of the slice shown by the RHI view in degrees
+    #[arg(long = "rhi-width", default_value_t = 5.0)]
+    pub width: f32,
+}
+
+impl RhiCut {
+    /// Whether a point in the scene lies within the slice. Points straight above or below the
+    /// scene origin are in every slice.
+    pub fn contains(&self, p: Vec3) -> bool {
+        if p.x.hypot(p.z) < 1.0 {
+            return true;
+        }
+        let offset = PolarVec3::from(p).azimuth - self.azimuth.to_radians();
+        let offset = (offset + PI).rem_euclid(TAU) - PI;
+        offset.abs() <= self.width.to_radians() / 2.0
+    }
+
+    /// Azimuth of the slice relative to a sensor's boresight
+    pub fn azimuth_from(&self, sensor: &Sensor) -> f32 {
+        let direction = pointing(self.azimuth.to_radians(), 0.0) * Vec3::Z;
+        PolarVec3::from(sensor.rotation.inverse() * direction).azimuth
+    }
+}
+
+/// How a view draws the scene
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    pub mode: RenderMode,
+    pub cut: RhiCut,
+}
+
+impl View {
+    /// Where a point in the scene is drawn, or NaN where the view leaves it out
+    pub fn project(&self, p: Vec3) -> Vec3 {
+        if self.mode == RenderMode::Rhi && !self.cut.contains(p) {
+            return Vec3::NAN;
+        }
+        self.mode.project(p)
+    }
+
+    pub fn marker_size(&self) -> f32 {
+        self.mode.marker_size()
+    }
+}
+
+/// The scene cameras and how each draws the scene, for systems that find where things are on
+/// the screen
+#[derive(SystemParam)]
+pub struct ViewCameras<'w, 's> {
+    cameras: Query<
+        'w,
+        's,
+        (
+            &'static Camera,
+            &'static GlobalTransform,
+            &'static RenderMode,
+        ),
+        With<SceneCamera>,
+    >,
+    cut: Res<'w, RhiCut>,
+}
+
+impl ViewCameras<'_, '_> {
+    pub fn iter(&self) -> impl Iterator<Item = (&Camera, &GlobalTransform, View)> {
+        self.cameras.iter().map(|(camera, transform, mode)| {
+            let view = View {
+                mode: *mode,
+                cut: *self.cut,
+            };
+            (camera, transform, view)
+        })
+    }
+}
+
+/// The active scene cameras, for systems that draw into every view
+#[derive(SystemParam)]
+pub struct Views<'w, 's> {
+    cameras: Query<'w, 's, (&'static SceneCamera, &'static Camera, &'static RenderMode)>,
+    cut: Res<'w, RhiCut>,
+    lines: ResMut<'w, ViewLines>,
+}
+
+impl Views<'_, '_> {
+    /// Each active view, with the lines drawn into it
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (View, &mut Lines)> {
+        let mut active = Vec::new();
+        for (place, camera, mode) in self.cameras.iter() {
+            if camera.is_active {
+                let view = View {
+                    mode: *mode,
+                    cut: *self.cut,
+                };
+                active.push((place.0, view));
+            }
+        }
+        let count = active.iter().map(|(index, _)| index + 1).max().unwrap_or(0);
+        if self.lines.0.len() < count {
+            self.lines.0.resize_with(count, Default::default);
+        }
+        self.lines
+            .0
+            .iter_mut()
+            .enumerate()
+            .filter_map(move |(index, lines)| {
+                let (_, view) = active.iter().find(|(i, _)| *i == index)?;
+                Some((*view, lines))
+            })
+    }
+}
+
+/// Focus and orthographic scale that fit the first sensor's FoV in view, found from the bounds
+/// of points spread through it
+fn framing(mode: RenderMode, sensor: &Sensor) -> (Vec3, f32) {
+    const STEPS: usize = 8;
+    let fov = &sensor.fov;
+    let lerp = |[from, to]: [f32; 2], i: usize| from + (to - from) * i as f32 / STEPS as f32;
+
+    let (mut min, mut max) = (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY));
+    // The sensor itself has no direction, so the nearest points are just in front of it
+    for range in [fov.min_range.max(1.0), fov.range] {
+        for i in 0..=STEPS {
+            for j in 0..=STEPS {
+                let p = PolarVec3::new(range, lerp(fov.az, i), lerp(fov.el, j));
+                let p = mode.project(sensor.to_scene(p));
+                min = min.min(p);
+                max = max.max(p);
+            }
+        }
+    }
+    let size = max - min;
+    // The cameras look along Z, so only X and Y are seen, with a margin around the FoV
+    (min + size / 2.0, size.x.max(size.y) * 0.55)
+}
+
+/// Move each camera to frame the scene whenever its render mode or the sensors change.
+/// Panning and zooming are left alone otherwise.
+pub fn retarget_camera(
+    sensors: Res<Sensors>,
+    mut previous: Local<HashMap<Entity, (Vec3, f32)>>,
+    mut cameras: Query<(Entity, &RenderMode, &mut PanOrbitCamera)>,
+) {
+    for (entity, mode, mut camera) in cameras.iter_mut() {
+        let framing = framing(*mode, &sensors.0[0]);
+        if previous.insert(entity, framing) == Some(framing) {
+            continue;
+        }
+
+        let (focus, scale) = framing;
+        camera.target_focus = focus;
+        camera.target_scale = scale;
+        camera.target_alpha = PI;
+        camera.target_beta = 0.0;
+    }
+}
+
+/// Give each scene camera its part of the window, turning off the second unless the screen is
+/// split
+pub fn layout_views(
+    split: Res<SplitScreen>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(&SceneCamera, &mut Camera)>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let size = UVec2::new(window.physical_width(), window.physical_height());
+    let columns = if split.0 { 2 } else { 1 };
+    let width = size.x / columns;
+    for (place, mut camera) in cameras.iter_mut() {
+        let index = place.0 as u32;
+        let is_active = index < columns;
+        let rect = (split.0 && is_active).then(|| {
+            let position = UVec2::new(index * width, 0);
+            (position, UVec2::new(width, size.y).max(UVec2::ONE))
+        });
+        // Only touch cameras that need to change, so they are not recomputed every frame
+        let current = camera
+            .viewport
+            .as_ref()
+            .map(|v| (v.physical_position, v.physical_size));
+        if camera.is_active == is_active && current == rect {
+            continue;
+        }
+        camera.is_active = is_active;
+        camera.viewport = rect.map(|(physical_position, physical_size)| Viewport {
+            physical_position,
+            physical_size,
+            ..Default::default()
+        });
+        // Inactive cameras are kept below the others, so they never take orbit input
+        camera.order = if is_active { place.0 as isize } else { -1 };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::math::Vec3;
+
+    use crate::polar::PolarVec3;
+    use crate::RenderMode;
+
+    use super::{RhiCut, View};
+
+    #[test]
+    fn azimuth_turns_left() {
+        let ahead = Vec3::from(PolarVec3::new(1000.0, 0.0, 0.1));
+        let left = Vec3::from(PolarVec3::new(1000.0, 0.2, 0.1));
+        // Cameras look along +Z, so the left of the screen is +X
+        for mode in [
+            RenderMode::Cartesian,
+            RenderMode::Spherical,
+            RenderMode::Ppi,
+            RenderMode::BScope,
+        ] {
+            assert!(mode.project(left).x > mode.project(ahead).x, "{:?}", mode);
+        }
+
+        // An RHI only shows its slice, turned to face the screen
+        let cut = RhiCut {
+            azimuth: 0.2f32.to_degrees(),
+            width: 5.0,
+        };
+        let view = View {
+            mode: RenderMode::Rhi,
+            cut,
+        };
+        assert!(view.project(ahead).is_nan());
+        assert_eq!(view.project(left), RenderMode::Rhi.project(left));
+        assert!(cut.contains(Vec3::Y * 1000.0));
+    }
+}
@@ -0,0 +1,240 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use bevy::ecs::system::Resource;
+use clap::{Parser, Subcommand};
+
+use crate::association::AssociationConfig;
+use crate::camera::RhiCut;
+use crate::coverage::CoverageMode;
+use crate::data::{Header, SimulationRun};
+use crate::frame::{Axes, Frame, StateOrder};
+use crate::grid::GridConfig;
+use crate::live::Source;
+use crate::metrics::{MetricConfig, RunMetrics};
+use crate::validate::{validate, Severity};
+use crate::RenderMode;
+
+/// Replay a radar simulation run
+#[derive(Parser, Debug, Resource)]
+#[command(version, about, subcommand_negates_reqs = true)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Simulation run to open, either one JSON step per line or the binary format
+    #[arg(required_unless_present = "live", conflicts_with = "live")]
+    pub path: Option<String>,
+
+    /// Listen for live steps on a TCP address instead of opening a run
+    #[arg(long, group = "live", value_name = "ADDRESS")]
+    pub listen: Option<String>,
+
+    /// Listen for live steps on a Unix socket instead of opening a run
+    #[arg(long, group = "live", value_name = "PATH")]
+    pub unix: Option<PathBuf>,
+
+    /// Read live steps from standard input instead of opening a run
+    #[arg(long, group = "live")]
+    pub stdin: bool,
+
+    /// Keep reading steps appended to the run while it is still being written
+    #[arg(long, conflicts_with = "live")]
+    pub follow: bool,
+
+    /// Map a binary run and only load the steps around the current time
+    #[arg(long, conflicts_with_all = ["live", "follow"])]
+    pub lazy: bool,
+
+    /// Length of the window of steps loaded by lazy loading, in seconds
+    #[arg(long, default_value_t = 60.0, requires = "lazy")]
+    pub window: f64,
+
+    /// Hold playback at the latest step received from a live source
+    #[arg(long)]
+    pub pin_latest: bool,
+
+    /// How the scene is initially projected, cycled with M
+    #[arg(long, value_enum, default_value_t = RenderMode::Cartesian)]
+    pub mode: RenderMode,
+
+    /// Start with the screen split between this view and the next, toggled with V
+    #[arg(long)]
+    pub split: bool,
+
+    /// Simulation seconds advanced each frame
+    #[arg(long, default_value_t = 0.01)]
+    pub speed: f64,
+
+    /// Simulation time to start playback at, in seconds
+    #[arg(long, default_value_t = 0.0)]
+    pub start: f64,
+
+    /// How long a sample remains valid without a following sample, in seconds
+    #[arg(long, default_value_t = 1.0)]
+    pub staleness: f64,
+
+    /// Maximum range of the first sensor's field of view in meters, overriding the run header
+    #[arg(long)]
+    pub fov_range: Option<f32>,
+
+    /// Total azimuth extent of the first sensor's field of view in degrees, overriding the run
+    /// header
+    #[arg(long)]
+    pub fov_az: Option<f32>,
+
+    /// Total elevation extent of the first sensor's field of view in degrees, overriding the run
+    /// header
+    #[arg(long)]
+    pub fov_el: Option<f32>,
+
+    /// Lowest elevation of the first sensor's field of view in degrees, overriding the run
+    /// header. The extent given by --fov-el then starts here instead of being centered.
+    #[arg(long, allow_negative_numbers = true)]
+    pub fov_min_el: Option<f32>,
+
+    /// Beam coverage heatmap to show initially, cycled with H
+    #[arg(long, value_enum, default_value_t = CoverageMode::Off)]
+    pub coverage: CoverageMode,
+
+    /// How far back beam dwells count towards the coverage heatmap, in seconds
+    #[arg(long, default_value_t = 10.0)]
+    pub coverage_window: f64,
+
+    /// Size of each coverage heatmap cell, in degrees
+    #[arg(long, default_value_t = 2.0)]
+    pub coverage_cell: f32,
+
+    /// Leave out steps that fail validation instead of refusing to load the run
+    #[arg(long, global = true)]
+    pub skip_invalid: bool,
+
+    /// Axes of the input states, overriding the run header
+    #[arg(long, value_enum)]
+    pub axes: Option<Axes>,
+
+    /// Layout of the input state vectors, overriding the run header
+    #[arg(long, value_enum)]
+    pub state_order: Option<StateOrder>,
+
+    #[command(flatten)]
+    pub metrics: MetricConfig,
+
+    #[command(flatten)]
+    pub association: AssociationConfig,
+
+    #[command(flatten)]
+    pub grid: GridConfig,
+
+    #[command(flatten)]
+    pub rhi: RhiCut,
+}
+
+impl Args {
+    /// The frame declared by a run header, with any overrides given on the command line
+    pub fn frame(&self, header: Frame) -> Frame {
+        Frame {
+            axes: self.axes.unwrap_or(header.axes),
+            order: self.state_order.unwrap_or(header.order),
+        }
+    }
+
+    /// A run header with any overrides given on the command line
+    pub fn header(&self, mut header: Header) -> Header {
+        header.frame = self.frame(header.frame);
+        let fov = &mut header.sensors[0].fov;
+        if let Some(range) = self.fov_range {
+            fov.range = range;
+        }
+        if let Some(az) = self.fov_az {
+            let az = az.to_radians();
+            let center = (fov.az[0] + fov.az[1]) / 2.0;
+            fov.az = [center - az / 2.0, center + az / 2.0];
+        }
+        let el = self.fov_el.map_or(fov.el[1] - fov.el[0], f32::to_radians);
+        match self.fov_min_el {
+            Some(min) => fov.el = [min.to_radians(), min.to_radians() + el],
+            None if self.fov_el.is_some() => {
+                let center = (fov.el[0] + fov.el[1]) / 2.0;
+                fov.el = [center - el / 2.0, center + el / 2.0];
+            }
+            None => {}
+        }
+        header
+    }
+
+    /// Where to read live steps from, if not replaying a complete run
+    pub fn live_source(&self) -> Option<Source> {
+        if let Some(address) = &self.listen {
+            Some(Source::Tcp(address.clone()))
+        } else if let Some(path) = &self.unix {
+            Some(Source::Unix(path.clone()))
+        } else if self.stdin {
+            Some(Source::Stdin)
+        } else if self.follow {
+            self.path.as_ref().map(|path| Source::Follow(path.into()))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the OSPA and GOSPA of a run at every step, without opening a window
+    Metrics {
+        /// Simulation run to evaluate
+        path: String,
+
+        #[command(flatten)]
+        config: MetricConfig,
+    },
+    /// Check a run for problems, listing every invalid line
+    Validate {
+        /// JSONL run to check
+        path: String,
+    },
+    /// Convert a run to the compact binary format, which loads much faster
+    Convert {
+        /// Run to convert, in either format
+        input: String,
+
+        /// Where to write the binary run
+        output: String,
+    },
+}
+
+impl Command {
+    pub fn run(&self, skip_invalid: bool) -> Result<()> {
+        match self {
+            Command::Metrics { path, config } => {
+                let sim = SimulationRun::new(path, skip_invalid)?;
+                RunMetrics::new(&sim, config).print();
+            }
+            Command::Validate { path } => {
+                let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+                let diagnostics = validate(BufReader::new(file))?;
+                for diagnostic in diagnostics.iter() {
+                    println!("{}", diagnostic);
+                }
+                let errors = diagnostics
+                    .iter()
+                    .filter(|d| d.severity == Severity::Error)
+                    .count();
+                println!("{} errors, {} warnings", errors, diagnostics.len() - errors);
+                if errors > 0 {
+                    bail!("{} is not valid", path);
+                }
+            }
+            Command::Convert { input, output } => {
+                let sim = SimulationRun::new(input, skip_invalid)?;
+                let file =
+                    File::create(output).with_context(|| format!("failed to create {}", output))?;
+                sim.write_binary(BufWriter::new(file))?;
+            }
+        }
+        Ok(())
+    }
+}
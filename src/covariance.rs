@@ -0,0 +1,147 @@
+use bevy::ecs::component::Component;
+use bevy::math::{Mat3, Vec3};
+
+use crate::polar::PolarVec3;
+use crate::timeseries::Interpolate;
+
+/// Position covariance of a track, in the scene frame
+#[derive(Clone, Debug, Default, Component)]
+pub struct Covariance(pub Mat3);
+
+impl Interpolate for Covariance {
+    fn interpolate(&self, next: &Self, s: f32) -> Self {
+        Self(self.0 * (1.0 - s) + next.0 * s)
+    }
+}
+
+/// Unpack a flat 6x6 state covariance, stored either as the full row-major matrix or as the
+/// row-major upper triangle.
+pub fn unpack_state_covariance(values: &[f32]) -> Option<[[f32; 6]; 6]> {
+    let mut matrix = [[0.0; 6]; 6];
+    match values.len() {
+        36 => {
+            for (i, row) in matrix.iter_mut().enumerate() {
+                row.copy_from_slice(&values[i * 6..(i + 1) * 6]);
+            }
+        }
+        21 => {
+            let indices = (0..6).flat_map(|i| (i..6).map(move |j| (i, j)));
+            for ((i, j), v) in indices.zip(values) {
+                matrix[i][j] = *v;
+                matrix[j][i] = *v;
+            }
+        }
+        _ => return None,
+    }
+    Some(matrix)
+}
+
+impl Covariance {
+    /// Extract the position block of a state covariance, where `axes` gives the index of the
+    /// state element mapped to each scene axis.
+    pub fn from_state(state: &[[f32; 6]; 6], axes: [usize; 3]) -> Self {
+        let cols = axes.map(|col| Vec3::from_array(axes.map(|row| state[row][col])));
+        Self(Mat3::from_cols(cols[0], cols[1], cols[2]))
+    }
+
+    /// Eigen-decomposition of the covariance as `(variance, axis)` pairs, using Jacobi rotations.
+    pub fn principal_axes(&self) -> [(f32, Vec3); 3] {
+        let mut a = self.0.to_cols_array_2d();
+        let mut v = Mat3::IDENTITY.to_cols_array_2d();
+
+        for _ in 0..32 {
+            // Find the largest off diagonal element
+            let (p, q) = [(0, 1), (0, 2), (1, 2)]
+                .into_iter()
+                .max_by(|(i, j), (k, l)| a[*i][*j].abs().total_cmp(&a[*k][*l].abs()))
+                .unwrap();
+            if a[p][q].abs() < 1e-9 * (a[p][p].abs() + a[q][q].abs()).max(f32::MIN_POSITIVE) {
+                break;
+            }
+
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            for row in a.iter_mut() {
+                let (akp, akq) = (row[p], row[q]);
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            a[p] = [0, 1, 2].map(|k| c * row_p[k] - s * row_q[k]);
+            a[q] = [0, 1, 2].map(|k| s * row_p[k] + c * row_q[k]);
+            for row in v.iter_mut() {
+                let (vp, vq) = (row[p], row[q]);
+                row[p] = c * vp - s * vq;
+                row[q] = s * vp + c * vq;
+            }
+        }
+
+        let v = Mat3::from_cols_array_2d(&v).transpose();
+        [0, 1, 2].map(|i| (a[i][i].max(0.0), v.col(i)))
+    }
+
+    /// Standard deviation in range, azimuth and elevation of a target at `pos`, from a first
+    /// order projection of the covariance through the polar transform.
+    pub fn polar_sigma(&self, pos: Vec3) -> PolarVec3 {
+        let r2 = pos.length_squared();
+        let r = r2.sqrt();
+        let h2 = pos.x * pos.x + pos.z * pos.z;
+        let h = h2.sqrt();
+
+        let d_range = pos / r;
+        let d_azimuth = Vec3::new(pos.z / h2, 0.0, -pos.x / h2);
+        let d_elevation = Vec3::new(-pos.y * pos.x / (r2 * h), h / r2, -pos.y * pos.z / (r2 * h));
+
+        let variance = |d: Vec3| d.dot(self.0 * d).max(0.0).sqrt();
+        PolarVec3::new(
+            variance(d_range),
+            variance(d_azimuth),
+            variance(d_elevation),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::math::{Mat3, Vec3};
+
+    use super::{unpack_state_covariance, Covariance};
+
+    #[test]
+    fn unpack_triangle() {
+        let values: Vec<f32> = (0..21).map(|v| v as f32).collect();
+        let full = unpack_state_covariance(&values).unwrap();
+        for (i, row) in full.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                assert_eq!(*value, full[j][i]);
+            }
+        }
+        assert_eq!(full[0][5], 5.0);
+        assert_eq!(full[1][1], 6.0);
+        assert_eq!(full[5][5], 20.0);
+        assert!(unpack_state_covariance(&values[..20]).is_none());
+    }
+
+    #[test]
+    fn principal_axes() {
+        let axis = Vec3::new(1.0, 1.0, 0.0).normalize();
+        let other = Vec3::new(-1.0, 1.0, 0.0).normalize();
+        let matrix = 9.0 * Mat3::from_cols(axis * axis.x, axis * axis.y, axis * axis.z)
+            + 4.0 * Mat3::from_cols(other * other.x, other * other.y, other * other.z)
+            + Mat3::from_cols(Vec3::ZERO, Vec3::ZERO, Vec3::Z);
+
+        let axes = Covariance(matrix).principal_axes();
+        for (variance, vector) in axes {
+            assert!((matrix * vector - variance * vector).length() < 0.001);
+        }
+
+        let mut variances = axes.map(|(v, _)| v);
+        variances.sort_by(f32::total_cmp);
+        assert!((variances[0] - 1.0).abs() < 0.001);
+        assert!((variances[1] - 4.0).abs() < 0.001);
+        assert!((variances[2] - 9.0).abs() < 0.001);
+    }
+}
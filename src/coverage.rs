@@ -0,0 +1,255 @@
+use bevy::ecs::system::{Query, Res, ResMut, Resource};
+use bevy::math::Vec2;
+use bevy::render::color::Color;
+
+use crate::beam::BeamState;
+use crate::camera::Views;
+use crate::fov::FoV;
+use crate::polar::PolarVec3;
+use crate::sensor::Sensors;
+use crate::timeseries::{Time, TimeSeries};
+
+/// What the coverage heatmap shows for each cell of the field of view
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum CoverageMode {
+    #[default]
+    Off,
+    /// How many beam samples in the window covered the cell
+    Dwell,
+    /// How long ago a beam last covered the cell
+    Revisit,
+}
+
+impl CoverageMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Dwell,
+            Self::Dwell => Self::Revisit,
+            Self::Revisit => Self::Off,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Cell {
+    dwells: u32,
+    last: Option<f64>,
+}
+
+/// Where beams have pointed over a sliding window, on a grid of angular cells over the FoV
+#[derive(Resource, Debug)]
+pub struct Coverage {
+    pub mode: CoverageMode,
+    /// How far back beam samples are counted, in seconds
+    pub window: f64,
+    /// Width and height of each cell, in radians
+    pub cell: f32,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+}
+
+impl Coverage {
+    pub fn new(mode: CoverageMode, window: f64, cell: f32) -> Self {
+        Self {
+            mode,
+            window,
+            cell,
+            columns: 0,
+            rows: 0,
+            cells: Vec::new(),
+        }
+    }
+
+    /// Recount the cells covered by beam samples within the window before `time`
+    fn accumulate<'a>(
+        &mut self,
+        fov: &FoV,
+        time: f64,
+        samples: impl Iterator<Item = &'a (f64, BeamState)>,
+    ) {
+        self.columns = ((fov.az[1] - fov.az[0]) / self.cell).ceil().max(1.0) as usize;
+        self.rows = ((fov.el[1] - fov.el[0]) / self.cell).ceil().max(1.0) as usize;
+        self.cells.clear();
+        self.cells.resize(self.columns * self.rows, Cell::default());
+
+        for (t, beam) in samples {
+            if *t > time || *t < time - self.window {
+                continue;
+            }
+            for index in self.footprint(fov, beam) {
+                let cell = &mut self.cells[index];
+                cell.dwells += 1;
+                cell.last = Some(cell.last.map_or(*t, |last| last.max(*t)));
+            }
+        }
+    }
+
+    /// Cells whose center is within the beam, or the cell the beam points into if none are.
+    /// The beam is treated as an ellipse in azimuth and elevation.
+    fn footprint(&self, fov: &FoV, beam: &BeamState) -> Vec<usize> {
+        let radius = beam.width / 2.0;
+        let center = Vec2::new(beam.target.azimuth, beam.target.elevation);
+        let column = |az: f32| ((az - fov.az[0]) / self.cell).floor() as isize;
+        let row = |el: f32| ((el - fov.el[0]) / self.cell).floor() as isize;
+        let in_grid = |c: isize, r: isize| {
+            (0..self.columns as isize).contains(&c) && (0..self.rows as isize).contains(&r)
+        };
+
+        let mut cells = Vec::new();
+        for r in row(center.y - radius.y)..=row(center.y + radius.y) {
+            for c in column(center.x - radius.x)..=column(center.x + radius.x) {
+                if in_grid(c, r)
+                    && ((self.center(fov, c as usize, r as usize) - center) / radius)
+                        .length_squared()
+                        <= 1.0
+                {
+                    cells.push(r as usize * self.columns + c as usize);
+                }
+            }
+        }
+
+        let (c, r) = (column(center.x), row(center.y));
+        if cells.is_empty() && in_grid(c, r) {
+            cells.push(r as usize * self.columns + c as usize);
+        }
+        cells
+    }
+
+    /// Azimuth and elevation of the center of a cell
+    fn center(&self, fov: &FoV, column: usize, row: usize) -> Vec2 {
+        Vec2::new(
+            fov.az[0] + (column as f32 + 0.5) * self.cell,
+            fov.el[0] + (row as f32 + 0.5) * self.cell,
+        )
+    }
+
+    /// Heat of each covered cell from 0 to 1, with its center
+    fn heat(&self, fov: &FoV, time: f64) -> Vec<(Vec2, f32)> {
+        let max = self
+            .cells
+            .iter()
+            .map(|c| c.dwells)
+            .max()
+            .unwrap_or_default();
+        let mut heat = Vec::new();
+        for (index, cell) in self.cells.iter().enumerate() {
+            let Some(last) = cell.last else {
+                continue;
+            };
+            let value = match self.mode {
+                CoverageMode::Off => continue,
+                CoverageMode::Dwell => cell.dwells as f32 / max as f32,
+                CoverageMode::Revisit => 1.0 - ((time - last) / self.window) as f32,
+            };
+            let center = self.center(fov, index % self.columns, index / self.columns);
+            heat.push((center, value.clamp(0.0, 1.0)));
+        }
+        heat
+    }
+}
+
+/// Blue for cold through to red for hot
+fn heat_color(value: f32) -> Color {
+    Color::hsla(240.0 * (1.0 - value), 1.0, 0.5, 0.3 + 0.7 * value)
+}
+
+/// Count the beams of the first sensor over its FoV
+pub fn update_coverage(
+    time: Res<Time>,
+    sensors: Res<Sensors>,
+    mut coverage: ResMut<Coverage>,
+    beams: Query<&TimeSeries<BeamState>>,
+) {
+    if coverage.mode == CoverageMode::Off {
+        return;
+    }
+    let start = time.0 - coverage.window;
+    let samples = beams
+        .iter()
+        .flat_map(|history| history.range(start, time.0))
+        .filter(|(_, beam)| beam.sensor == 0);
+    coverage.accumulate(&sensors.0[0].fov, time.0, samples);
+}
+
+/// Draw each covered cell on the far surface of the FoV, colored by its heat
+pub fn render_coverage(
+    mut views: Views,
+    time: Res<Time>,
+    sensors: Res<Sensors>,
+    coverage: Res<Coverage>,
+) {
+    if coverage.mode == CoverageMode::Off {
+        return;
+    }
+    let sensor = &sensors.0[0];
+    // Leave a gap between cells so neighbors can be told apart
+    let half = coverage.cell * 0.45;
+    for (view, gizmos) in views.iter_mut() {
+        for (center, value) in coverage.heat(&sensor.fov, time.0) {
+            let corners = [
+                (-1.0, -1.0),
+                (1.0, -1.0),
+                (1.0, 1.0),
+                (-1.0, 1.0),
+                (-1.0, -1.0),
+            ]
+            .map(|(x, y)| {
+                let corner =
+                    PolarVec3::new(sensor.fov.range, center.x + x * half, center.y + y * half);
+                view.project(sensor.to_scene(corner))
+            });
+            gizmos.linestrip(corners, heat_color(value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::FRAC_PI_2;
+
+    use bevy::math::Vec2;
+
+    use crate::beam::BeamState;
+    use crate::fov::FoV;
+    use crate::polar::PolarVec3;
+
+    use super::{Coverage, CoverageMode};
+
+    #[test]
+    fn dwells_and_revisits() {
+        let fov = FoV::new(1000.0, FRAC_PI_2, FRAC_PI_2);
+        let beam = |az: f32, width: f32| BeamState {
+            width: Vec2::splat(width),
+            target: PolarVec3::new(1000.0, az, 0.0),
+            ..Default::default()
+        };
+        let samples = [
+            (0.0, beam(0.0, 0.01)),
+            (1.0, beam(0.0, 0.01)),
+            (2.0, beam(0.5, 0.01)),
+            (3.0, beam(-0.5, 0.2)),
+            // Outside the window either side
+            (-5.0, beam(0.5, 0.01)),
+            (9.0, beam(0.5, 0.01)),
+        ];
+
+        let mut coverage = Coverage::new(CoverageMode::Dwell, 4.0, 0.1);
+        coverage.accumulate(&fov, 3.0, samples.iter());
+        let dwells: Vec<u32> = coverage.cells.iter().map(|c| c.dwells).collect();
+        assert_eq!(dwells.iter().max(), Some(&2));
+        // The narrow beams each cover one cell, the wide one several
+        assert_eq!(dwells.iter().filter(|d| **d > 0).count(), 2 + 4);
+
+        coverage.mode = CoverageMode::Revisit;
+        let heat = coverage.heat(&fov, 3.0);
+        let at = |az: f32| {
+            heat.iter()
+                .find(|(center, _)| (center.x - az).abs() < 0.05 && center.y.abs() < 0.05)
+                .map(|(_, value)| *value)
+        };
+        assert_eq!(at(0.0), Some(0.5));
+        assert_eq!(at(0.5), Some(0.75));
+        assert_eq!(at(-0.5), Some(1.0));
+    }
+}
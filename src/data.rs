@@ -1,29 +1,124 @@
-use anyhow::Result;
-use serde::Deserialize;
-use std::collections::HashSet;
+use anyhow::{bail, Context, Result};
+use bevy::ecs::entity::Entity;
+use bevy::ecs::event::EventReader;
+use bevy::ecs::query::{With, Without};
+use bevy::ecs::system::{Commands, Query, Res, ResMut, Resource, SystemParam};
+use bevy::input::keyboard::KeyCode;
+use bevy::input::Input;
+use bevy::log::{error, info, warn};
+use bevy::math::Vec2;
+use bevy::window::FileDragAndDrop;
+use memmap2::Mmap;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::{collections::HashMap, fs::File};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Mutex;
+use std::thread;
 
-use crate::beam::{BeamBundle, BeamState};
+use crate::association::{associate_run, Association, AssociationConfig, Associator};
+use crate::beam::{BeamBundle, BeamKey, BeamState};
+use crate::binary;
+use crate::cli::Args;
+use crate::covariance::{unpack_state_covariance, Covariance};
+use crate::frame::Frame;
+use crate::mapped::LazyRun;
 use crate::polar::PolarVec3;
+use crate::sensor::{Sensor, SensorConfig, Sensors};
 use crate::state::State;
-use crate::timeseries::{Active, TimeSeries};
+use crate::timeseries::{Active, Time, TimeSeries};
+use crate::track::{self, TrackBundle};
+use crate::truth::{Truth, TruthBundle};
+use crate::validate::{Severity, Validator};
 
-const MAX_RANGE: f32 = 200_000.0;
+/// Limits the broadening of steered beams, which is unbounded at 90 degrees off the boresight
+const MIN_SCAN_COS: f32 = 0.1;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Beam {
+    /// Identifies the physical beam across steps. Without one, beams are identified by their
+    /// position in the step, apart from any ids.
+    #[serde(default)]
+    pub id: Option<u32>,
     pub width: f32,
+    /// Azimuth and elevation from the sensor boresight in radians, whatever the header frame
     pub position: [f32; 2],
+    /// Azimuth width, if different from `width`
+    #[serde(default)]
+    pub az_width: Option<f32>,
+    /// Elevation width, if different from `width`
+    #[serde(default)]
+    pub el_width: Option<f32>,
+    /// Whether the beam is electronically steered, so it broadens away from the boresight.
+    /// The widths are then those of a beam along the boresight.
+    #[serde(default)]
+    pub steered: bool,
+    /// Start of the range gate, in meters
+    #[serde(default)]
+    pub min_range: Option<f32>,
+    /// End of the range gate, in meters
+    #[serde(default)]
+    pub max_range: Option<f32>,
+    /// Waveform or radar mode the dwell used, such as search or track
+    #[serde(default)]
+    pub waveform: Option<String>,
+    /// Length of the dwell, in seconds
+    #[serde(default)]
+    pub dwell: Option<f32>,
+    /// Index of the sensor in the header that the beam is from
+    #[serde(default)]
+    pub sensor: u32,
 }
 
-#[derive(Debug, Deserialize)]
+impl Beam {
+    /// Identity of the beam, given its position in the step
+    pub fn key(&self, position: usize) -> BeamKey {
+        match self.id {
+            Some(id) => BeamKey::Id(id),
+            None => BeamKey::Position(position),
+        }
+    }
+
+    /// The beam's state in the scene, where `sensors` are the run's sensors. A beam from a
+    /// sensor that is not in the header is drawn from the first.
+    pub fn state(&self, key: BeamKey, sensors: &[Sensor]) -> BeamState {
+        let sensor_index = match self.sensor as usize {
+            i if i < sensors.len() => i,
+            _ => 0,
+        };
+        let sensor = &sensors[sensor_index];
+        let [az, el] = self.position;
+        let mut width = Vec2::new(
+            self.az_width.unwrap_or(self.width),
+            self.el_width.unwrap_or(self.width),
+        );
+        if self.steered {
+            // Each width broadens by 1/cos of the scan angle in its own plane
+            width /= Vec2::new(az.cos(), el.cos()).max(Vec2::splat(MIN_SCAN_COS));
+        }
+        BeamState {
+            width,
+            target: PolarVec3::new(self.max_range.unwrap_or(sensor.fov.range), az, el),
+            min_range: self.min_range.unwrap_or(sensor.fov.min_range),
+            key,
+            waveform: self.waveform.clone(),
+            dwell: self.dwell,
+            sensor: sensor_index,
+            origin: sensor.origin,
+            orientation: sensor.rotation,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Track {
     pub state: [f32; 6],
     pub uncertainty: Vec<f32>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, PartialEq, Deserialize)]
 pub struct Step {
     pub elapsed: f64,
     pub truths: HashMap<String, [f32; 6]>,
@@ -31,76 +126,520 @@ pub struct Step {
     pub beams: Vec<Beam>,
 }
 
+impl Track {
+    pub fn state(&self, frame: &Frame) -> State {
+        frame.state(&self.state)
+    }
+
+    /// Position covariance in the scene frame, zero if the uncertainty could not be unpacked
+    pub fn covariance(&self, frame: &Frame) -> Covariance {
+        unpack_state_covariance(&self.uncertainty)
+            .map(|full| frame.covariance(&full))
+            .unwrap_or_default()
+    }
+}
+
+/// Optional first line of a run, describing how to interpret its steps
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Header {
+    /// Frame of the truth and track states. Beam positions are angles from their sensor's
+    /// boresight rather than vectors, so they are the same in every frame.
+    #[serde(default)]
+    pub frame: Frame,
+    /// Sensors that beams can be from, of which there must be at least one. The first is at
+    /// the origin of the frame unless given a position.
+    #[serde(default = "default_sensors", deserialize_with = "at_least_one")]
+    pub sensors: Vec<SensorConfig>,
+}
+
+fn default_sensors() -> Vec<SensorConfig> {
+    vec![SensorConfig::default()]
+}
+
+fn at_least_one<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<SensorConfig>, D::Error> {
+    let sensors = Vec::<SensorConfig>::deserialize(deserializer)?;
+    if sensors.is_empty() {
+        return Err(de::Error::invalid_length(0, &"at least one sensor"));
+    }
+    Ok(sensors)
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Self {
+            frame: Frame::default(),
+            sensors: default_sensors(),
+        }
+    }
+}
+
+impl Header {
+    /// Sensors placed in the scene
+    pub fn sensors(&self) -> Vec<Sensor> {
+        self.sensors
+            .iter()
+            .enumerate()
+            .map(|(index, config)| Sensor::new(index, config, &self.frame))
+            .collect()
+    }
+}
+
+/// A line of a JSONL run
+pub enum Line {
+    Header(Header),
+    Step(Step),
+}
+
+impl Line {
+    pub fn parse(line: &str) -> Result<Self> {
+        let header = match serde_json::from_str(line) {
+            Ok(header) => return Ok(Self::Header(header)),
+            Err(e) => e,
+        };
+        serde_json::from_str(line).map(Self::Step).or_else(|step| {
+            // Every step has an elapsed time, so a line without one was meant as a header
+            let is_step = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(line)
+                .map_or(true, |object| object.contains_key("elapsed"));
+            match is_step {
+                true => Err(step).context("invalid step"),
+                false => Err(header).context("invalid header"),
+            }
+        })
+    }
+}
+
+/// Samples of a single entity, each tagged with the index of the step it came from
+#[derive(Debug, PartialEq)]
+pub struct Column<T> {
+    pub steps: Vec<u32>,
+    pub values: Vec<T>,
+}
+
+impl<T> Default for Column<T> {
+    fn default() -> Self {
+        Self {
+            steps: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<T> Column<T> {
+    fn push(&mut self, step: u32, value: T) {
+        self.steps.push(step);
+        self.values.push(value);
+    }
+
+    /// Samples paired with the elapsed time of their step
+    fn history<'a>(&'a self, elapsed: &'a [f64]) -> impl Iterator<Item = (f64, &'a T)> {
+        self.steps
+            .iter()
+            .zip(self.values.iter())
+            .map(|(step, value)| (elapsed[*step as usize], value))
+    }
+}
+
+/// A complete simulation run, stored as the history of each entity rather than step by step
+#[derive(Resource, Default, Debug, PartialEq)]
 pub struct SimulationRun {
-    steps: Vec<Step>,
+    /// Elapsed time of each step
+    pub elapsed: Vec<f64>,
+    pub header: Header,
+    pub truths: BTreeMap<String, Column<[f32; 6]>>,
+    pub tracks: BTreeMap<String, Column<Track>>,
+    /// Beams of each step
+    pub beams: Vec<Vec<Beam>>,
 }
 
 impl SimulationRun {
-    pub fn new(path: &str) -> Result<Self> {
-        let file = File::open(path)?;
+    /// Open a run in either the JSONL or binary format. Invalid JSONL steps fail the load
+    /// unless `skip_invalid` is set, in which case they are left out.
+    pub fn new(path: impl AsRef<Path>, skip_invalid: bool) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
         let mut reader = BufReader::new(file);
 
-        let mut steps = Vec::new();
-        loop {
+        let result = if binary::is_binary(&mut reader)? {
+            // SAFETY: runs are written once by the converter and not modified while being read
+            let bytes = unsafe { Mmap::map(reader.get_ref()) }
+                .with_context(|| format!("failed to map {}", path.display()))?;
+            Self::read_binary(&bytes)
+        } else {
+            Self::from_jsonl(reader, skip_invalid)
+        };
+        result.with_context(|| format!("failed to load {}", path.display()))
+    }
+
+    /// Read a run with one JSON step per line
+    pub fn from_jsonl(mut reader: impl BufRead, skip_invalid: bool) -> Result<Self> {
+        let mut run = Self::default();
+        let mut validator = Validator::default();
+        for line in 1.. {
             let mut buf = String::new();
             let size = reader.read_line(&mut buf)?;
             if size == 0 {
                 break;
             }
-            let step = serde_json::from_str(&buf)?;
-            steps.push(step);
+            if buf.trim().is_empty() {
+                continue;
+            }
+
+            let error = match Line::parse(&buf) {
+                Ok(Line::Header(header)) if line == 1 => {
+                    validator.sensors = header.sensors.len();
+                    run.header = header;
+                    continue;
+                }
+                Ok(Line::Header(_)) => "header must be the first line".to_string(),
+                Ok(Line::Step(step)) => {
+                    let problems = validator.check(&step);
+                    match problems.into_iter().find(|(s, _)| *s == Severity::Error) {
+                        None => {
+                            run.push(step);
+                            continue;
+                        }
+                        Some((_, message)) => message,
+                    }
+                }
+                Err(e) => format!("{:#}", e),
+            };
+
+            if !skip_invalid {
+                bail!(
+                    "line {}: {}\n\nUse `validate` to list every problem, or --skip-invalid to leave out invalid steps",
+                    line,
+                    error
+                );
+            }
+            warn!("line {}: skipping: {}", line, error);
         }
 
-        Ok(Self { steps })
+        Ok(run)
     }
 
-    pub fn truths(&self) -> Vec<(TimeSeries<State>, State, Active)> {
-        let mut truth_ids = HashSet::new();
-        for step in self.steps.iter() {
-            truth_ids.extend(step.truths.keys())
+    /// Append a step to the end of the run
+    pub fn push(&mut self, step: Step) {
+        let index = self.elapsed.len() as u32;
+        self.elapsed.push(step.elapsed);
+        for (id, truth) in step.truths {
+            self.truths.entry(id).or_default().push(index, truth);
         }
+        for (id, track) in step.tracks {
+            self.tracks.entry(id).or_default().push(index, track);
+        }
+        self.beams.push(step.beams);
+    }
 
-        let mut truths = Vec::with_capacity(truth_ids.len());
-        for truth_id in truth_ids.iter() {
-            let mut history = Vec::new();
-            for step in self.steps.iter() {
-                if let Some(truth) = step.truths.get(truth_id.as_str()) {
-                    history.push((
-                        step.elapsed,
-                        State::default()
-                            //.with_xyz(truth[0], truth[2], truth[4])
-                            .with_xyz(truth[2], truth[4], truth[0])
-                            .with_vel(truth[1], truth[3], truth[5]),
-                    ))
+    /// Rebuild each step of the run in order
+    pub fn steps(&self) -> impl Iterator<Item = Step> + '_ {
+        let mut truths: Vec<_> = self.truths.iter().map(|(id, c)| (id, c, 0)).collect();
+        let mut tracks: Vec<_> = self.tracks.iter().map(|(id, c)| (id, c, 0)).collect();
+
+        self.elapsed
+            .iter()
+            .enumerate()
+            .map(move |(index, elapsed)| {
+                // Each column's cursor sits on its first sample not yet emitted
+                fn take<T: Clone>(
+                    columns: &mut [(&String, &Column<T>, usize)],
+                    index: usize,
+                ) -> HashMap<String, T> {
+                    columns
+                        .iter_mut()
+                        .filter(|(_, column, cursor)| {
+                            column.steps.get(*cursor) == Some(&(index as u32))
+                        })
+                        .map(|(id, column, cursor)| {
+                            *cursor += 1;
+                            (id.to_string(), column.values[*cursor - 1].clone())
+                        })
+                        .collect()
+                }
+
+                Step {
+                    elapsed: *elapsed,
+                    truths: take(&mut truths, index),
+                    tracks: take(&mut tracks, index),
+                    beams: self.beams[index].clone(),
                 }
+            })
+    }
+
+    /// Spawn an entity for every truth, track and beam in the run, and place its sensors
+    pub fn spawn(&self, commands: &mut Commands, association: &AssociationConfig) {
+        commands.insert_resource(Sensors(self.header.sensors()));
+        commands.spawn_batch(self.truths());
+        commands.spawn_batch(self.tracks(association));
+        commands.spawn_batch(self.beams());
+    }
+
+    pub fn truths(&self) -> Vec<TruthBundle> {
+        self.truths
+            .iter()
+            .filter_map(|(id, column)| {
+                let history = column
+                    .history(&self.elapsed)
+                    .map(|(elapsed, truth)| (elapsed, self.header.frame.state(truth)))
+                    .collect();
+                TruthBundle::new(id.to_string(), history)
+            })
+            .collect()
+    }
+
+    pub fn tracks(&self, association: &AssociationConfig) -> Vec<TrackBundle> {
+        let frame = &self.header.frame;
+        let mut associations = associate_run(self.steps(), frame, association);
+
+        let mut tracks = Vec::with_capacity(self.tracks.len());
+        for (track_id, column) in self.tracks.iter() {
+            let mut history = Vec::new();
+            let mut covariances = Vec::new();
+            for (elapsed, track) in column.history(&self.elapsed) {
+                history.push((elapsed, track.state(frame)));
+                covariances.push((elapsed, track.covariance(frame)));
             }
-            let first = history[0].1.clone();
-            truths.push((TimeSeries::new(history), first, Active(false)))
+            let associations = associations.remove(track_id).unwrap_or_default();
+            tracks.extend(TrackBundle::new(
+                track_id.to_string(),
+                history,
+                covariances,
+                associations,
+            ))
         }
 
-        truths
+        tracks
     }
 
     pub fn beams(&self) -> Vec<BeamBundle> {
-        let mut beams = Vec::new();
-        for index in 0..4 {
-            let mut history = Vec::new();
-            for step in self.steps.iter() {
-                let beam = &step.beams[index];
-                let beam_pos = PolarVec3::new(MAX_RANGE, beam.position[0], beam.position[1]);
-                let state = BeamState {
-                    width: beam.width,
-                    target: beam_pos,
-                    index,
-                };
-                history.push((step.elapsed, state))
+        let sensors = self.header.sensors();
+        let mut histories: BTreeMap<BeamKey, Vec<(f64, BeamState)>> = BTreeMap::new();
+        for (elapsed, beams) in self.elapsed.iter().zip(self.beams.iter()) {
+            for (position, beam) in beams.iter().enumerate() {
+                let key = beam.key(position);
+                histories
+                    .entry(key)
+                    .or_default()
+                    .push((*elapsed, beam.state(key, &sensors)));
             }
-            beams.push(BeamBundle {
-                state: history[0].1.clone(),
-                active: Active(true),
-                history: TimeSeries::new(history),
-            })
         }
-        beams
+        histories
+            .into_values()
+            .filter_map(BeamBundle::new)
+            .collect()
+    }
+}
+
+type NewTrack = (
+    Vec<(f64, State)>,
+    Vec<(f64, Covariance)>,
+    Vec<(f64, Association)>,
+);
+
+type TrackHistories<'a> = (
+    Entity,
+    &'a track::Track,
+    &'a mut TimeSeries<State>,
+    &'a mut TimeSeries<Covariance>,
+    &'a mut TimeSeries<Association>,
+);
+
+/// The histories of every spawned truth, track and beam, for runs that arrive or are loaded a
+/// chunk at a time
+#[derive(SystemParam)]
+pub struct Histories<'w, 's> {
+    commands: Commands<'w, 's>,
+    truths: Query<
+        'w,
+        's,
+        (Entity, &'static Truth, &'static mut TimeSeries<State>),
+        Without<track::Track>,
+    >,
+    tracks: Query<'w, 's, TrackHistories<'static>, Without<Truth>>,
+    beams: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static BeamState,
+            &'static mut TimeSeries<BeamState>,
+        ),
+    >,
+}
+
+impl Histories<'_, '_> {
+    /// Append steps to the entity histories, spawning entities for unseen ids
+    pub fn add(
+        &mut self,
+        steps: impl IntoIterator<Item = Step>,
+        frame: &Frame,
+        sensors: &[Sensor],
+        associator: &mut Associator,
+        config: &AssociationConfig,
+    ) {
+        let truth_entities: HashMap<String, Entity> = self
+            .truths
+            .iter()
+            .map(|(e, truth, _)| (truth.0.clone(), e))
+            .collect();
+        let track_entities: HashMap<String, Entity> = self
+            .tracks
+            .iter()
+            .map(|(e, track, ..)| (track.0.clone(), e))
+            .collect();
+        let beam_entities: HashMap<BeamKey, Entity> = self
+            .beams
+            .iter()
+            .map(|(e, beam, _)| (beam.key, e))
+            .collect();
+
+        let mut new_truths: HashMap<String, Vec<(f64, State)>> = HashMap::new();
+        let mut new_tracks: HashMap<String, NewTrack> = HashMap::new();
+        let mut new_beams: HashMap<BeamKey, Vec<(f64, BeamState)>> = HashMap::new();
+
+        for step in steps {
+            let time = step.elapsed;
+
+            for (id, truth) in step.truths.iter() {
+                let state = frame.state(truth);
+                match truth_entities.get(id) {
+                    Some(entity) => self.truths.get_mut(*entity).unwrap().2.push(time, state),
+                    None => new_truths
+                        .entry(id.clone())
+                        .or_default()
+                        .push((time, state)),
+                }
+            }
+
+            for (id, association) in associator.step(&step, frame, config) {
+                let track = &step.tracks[&id];
+                let (state, covariance) = (track.state(frame), track.covariance(frame));
+                match track_entities.get(&id) {
+                    Some(entity) => {
+                        let (_, _, mut history, mut covariances, mut associations) =
+                            self.tracks.get_mut(*entity).unwrap();
+                        history.push(time, state);
+                        covariances.push(time, covariance);
+                        associations.push(time, association);
+                    }
+                    None => {
+                        let (history, covariances, associations) =
+                            new_tracks.entry(id).or_default();
+                        history.push((time, state));
+                        covariances.push((time, covariance));
+                        associations.push((time, association));
+                    }
+                }
+            }
+
+            for (position, beam) in step.beams.iter().enumerate() {
+                let key = beam.key(position);
+                let state = beam.state(key, sensors);
+                match beam_entities.get(&key) {
+                    Some(entity) => self.beams.get_mut(*entity).unwrap().2.push(time, state),
+                    None => new_beams.entry(key).or_default().push((time, state)),
+                }
+            }
+        }
+
+        let truths = new_truths
+            .into_iter()
+            .filter_map(|(id, history)| TruthBundle::new(id, history));
+        let tracks =
+            new_tracks
+                .into_iter()
+                .filter_map(|(id, (history, covariances, associations))| {
+                    TrackBundle::new(id, history, covariances, associations)
+                });
+        let beams = new_beams.into_values().filter_map(BeamBundle::new);
+        self.commands.spawn_batch(truths);
+        self.commands.spawn_batch(tracks);
+        self.commands.spawn_batch(beams);
+    }
+
+    /// Drop the samples outside the two times. Entities left without samples are kept, so
+    /// they pick up where they left off if their ids are seen again.
+    pub fn retain(&mut self, start: f64, end: f64) {
+        for (_, _, mut history) in self.truths.iter_mut() {
+            history.retain(start, end);
+        }
+        for (_, _, mut history, mut covariances, mut associations) in self.tracks.iter_mut() {
+            history.retain(start, end);
+            covariances.retain(start, end);
+            associations.retain(start, end);
+        }
+        for (_, _, mut history) in self.beams.iter_mut() {
+            history.retain(start, end);
+        }
+    }
+}
+
+/// A run file being picked with the open dialog, which runs on its own thread so the window
+/// keeps drawing while it is open
+#[derive(Resource, Default)]
+pub struct OpenDialog(Option<Mutex<Receiver<PathBuf>>>);
+
+/// Show the open dialog when O is pressed, unless it is already open
+pub fn open_run_dialog(keycode: Res<Input<KeyCode>>, mut dialog: ResMut<OpenDialog>) {
+    if !keycode.just_pressed(KeyCode::O) || dialog.0.is_some() {
+        return;
+    }
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        if let Some(path) = rfd::FileDialog::new().set_title("Open run").pick_file() {
+            // The receiver is only gone if the app is closing
+            let _ = sender.send(path);
+        }
+    });
+    dialog.0 = Some(Mutex::new(receiver));
+}
+
+/// Replace the current run with any run file dropped onto the window or picked with the open
+/// dialog
+pub fn load_opened_runs(
+    mut commands: Commands,
+    mut events: EventReader<FileDragAndDrop>,
+    mut dialog: ResMut<OpenDialog>,
+    args: Res<Args>,
+    mut time: ResMut<Time>,
+    entities: Query<Entity, With<Active>>,
+) {
+    let mut paths: Vec<PathBuf> = events
+        .read()
+        .filter_map(|event| match event {
+            FileDragAndDrop::DroppedFile { path_buf, .. } => Some(path_buf.clone()),
+            _ => None,
+        })
+        .collect();
+    let picked = dialog.0.as_ref().map(|r| r.lock().unwrap().try_recv());
+    match picked {
+        Some(Ok(path)) => {
+            paths.push(path);
+            dialog.0 = None;
+        }
+        // The dialog was closed without picking a file
+        Some(Err(TryRecvError::Disconnected)) => dialog.0 = None,
+        _ => {}
+    }
+
+    for path in paths {
+        let mut sim = match SimulationRun::new(&path, args.skip_invalid) {
+            Ok(sim) => sim,
+            Err(e) => {
+                error!("{:#}", e);
+                continue;
+            }
+        };
+        sim.header = args.header(sim.header);
+        info!("Loaded {}", path.display());
+
+        for entity in entities.iter() {
+            commands.entity(entity).despawn();
+        }
+        sim.spawn(&mut commands, &args.association);
+        commands.insert_resource(sim);
+        commands.remove_resource::<LazyRun>();
+        time.0 = 0.0;
     }
 }
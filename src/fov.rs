@@ -1,116 +1,114 @@
 use std::f32::consts::FRAC_PI_2;
 
-use bevy::{
-    ecs::system::Res,
-    ecs::system::Resource,
-    gizmos::gizmos::Gizmos,
-    math::{Quat, Vec2, Vec3},
-    render::color::Color,
-};
+use bevy::{ecs::system::Res, math::Vec3, render::color::Color};
+use serde::{Deserialize, Serialize};
 
-use crate::{polar::PolarVec3, RenderMode};
+use crate::{camera::Views, polar::PolarVec3, sensor::Sensors, RenderMode};
 
-#[derive(Resource)]
+/// Number of segments in each drawn edge of a FoV
+const EDGE_STEPS: usize = 5;
+
+/// What a sensor can see, relative to its boresight. Angles are in radians.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct FoV {
-    range: f32,
-    az: f32,
-    el: f32,
+    pub range: f32,
+    #[serde(default)]
+    pub min_range: f32,
+    /// Azimuth limits, from right to left
+    pub az: [f32; 2],
+    /// Elevation limits, from bottom to top
+    pub el: [f32; 2],
 }
 
-impl Default for FoV {
-    fn default() -> Self {
+impl FoV {
+    /// A FoV centered on the boresight, with the given total azimuth and elevation extents
+    pub fn new(range: f32, az: f32, el: f32) -> Self {
         Self {
-            range: 200_000.0,
-            az: FRAC_PI_2,
-            el: FRAC_PI_2,
+            range,
+            min_range: 0.0,
+            az: [-az / 2.0, az / 2.0],
+            el: [-el / 2.0, el / 2.0],
         }
     }
+
+    /// Points along the edge between two corners, at the given range
+    fn edge(&self, range: f32, from: (f32, f32), to: (f32, f32)) -> Vec<PolarVec3> {
+        (0..=EDGE_STEPS)
+            .map(|i| {
+                let s = i as f32 / EDGE_STEPS as f32;
+                PolarVec3::new(
+                    range,
+                    from.0 + (to.0 - from.0) * s,
+                    from.1 + (to.1 - from.1) * s,
+                )
+            })
+            .collect()
+    }
 }
 
-#[derive(Resource)]
-pub struct MaxRange(f32);
+impl Default for FoV {
+    fn default() -> Self {
+        Self::new(200_000.0, FRAC_PI_2, FRAC_PI_2)
+    }
+}
 
-pub fn render_fov(mode: Res<RenderMode>, fov: Res<FoV>, mut gizmos: Gizmos) {
+pub fn render_fov(mut views: Views, sensors: Res<Sensors>) {
     let color = Color::GRAY;
-    match mode.as_ref() {
-        RenderMode::Spherical => {
-            let size = Vec2::new(fov.az, fov.el);
-            gizmos.rect(Vec3::Z * fov.range, Quat::default(), size, color);
-        }
-        RenderMode::Cartesian => {
-            let tl = PolarVec3::new(fov.range, -fov.az / 2.0, fov.el / 2.0);
-            let tr = PolarVec3::new(fov.range, fov.az / 2.0, fov.el / 2.0);
-            let bl = PolarVec3::new(fov.range, -fov.az / 2.0, -fov.el / 2.0);
-            let br = PolarVec3::new(fov.range, fov.az / 2.0, -fov.el / 2.0);
-            gizmos.line(Vec3::default(), tl.into(), color);
-            gizmos.line(Vec3::default(), tr.into(), color);
-            gizmos.line(Vec3::default(), bl.into(), color);
-            gizmos.line(Vec3::default(), br.into(), color);
-
-            let top = PolarVec3::new(fov.range, 0.0, fov.el / 2.0);
-            let bottom = PolarVec3::new(fov.range, 0.0, -fov.el / 2.0);
-            gizmos.line(Vec3::default(), top.into(), color);
-            gizmos.line(Vec3::default(), bottom.into(), color);
-
-            let steps = 5;
-
-            // The back two arcs
-            let positions: Vec<_> = (0..steps + 1)
-                .map(|i| {
-                    let az = (fov.az / (steps as f32)) * (i as f32) - (fov.az / 2.0);
-                    let p = PolarVec3::new(fov.range, az, 0.0);
-                    p.into()
-                })
-                .collect();
-            gizmos.linestrip(positions, color);
-
-            let positions: Vec<_> = (0..steps + 1)
-                .map(|i| {
-                    let el = (fov.el / (steps as f32)) * (i as f32) - (fov.el / 2.0);
-                    dbg!(el);
-                    let p = PolarVec3::new(fov.range, 0.0, el);
-                    p.into()
-                })
-                .collect();
-            gizmos.linestrip(positions, color);
-
-            // Top and bottom arc
-            let positions: Vec<_> = (0..steps + 1)
-                .map(|i| {
-                    let az = (fov.az / (steps as f32)) * (i as f32) - (fov.az / 2.0);
-                    let p = PolarVec3::new(fov.range, az, fov.el / 2.0);
-                    p.into()
-                })
-                .collect();
-            gizmos.linestrip(positions, color);
-
-            let positions: Vec<_> = (0..steps + 1)
-                .map(|i| {
-                    let az = (fov.az / (steps as f32)) * (i as f32) - (fov.az / 2.0);
-                    let p = PolarVec3::new(fov.range, az, -fov.el / 2.0);
-                    p.into()
-                })
-                .collect();
-            gizmos.linestrip(positions, color);
+    for (view, gizmos) in views.iter_mut() {
+        for sensor in sensors.0.iter() {
+            let fov = &sensor.fov;
+            // An RHI shows the FoV's profile through its slice
+            let [right, left] = match view.mode {
+                RenderMode::Rhi => {
+                    let az = view.cut.azimuth_from(sensor);
+                    if az < fov.az[0] || az > fov.az[1] {
+                        continue;
+                    }
+                    [az; 2]
+                }
+                _ => fov.az,
+            };
+            let [bottom, top] = fov.el;
+            let corners = [(left, top), (right, top), (right, bottom), (left, bottom)];
 
-            // Left and right arc
-            let positions: Vec<_> = (0..steps + 1)
-                .map(|i| {
-                    let el = (fov.el / (steps as f32)) * (i as f32) - (fov.el / 2.0);
-                    let p = PolarVec3::new(fov.range, fov.az / 2.0, el);
-                    p.into()
-                })
-                .collect();
-            gizmos.linestrip(positions, color);
+            // The far surface, and the near one if the FoV starts away from the sensor
+            let mut ranges = vec![fov.range];
+            if fov.min_range > 0.0 {
+                ranges.push(fov.min_range);
+            }
+            for range in ranges {
+                for i in 0..corners.len() {
+                    let edge = fov.edge(range, corners[i], corners[(i + 1) % corners.len()]);
+                    gizmos.linestrip(
+                        edge.into_iter().map(|p| view.project(sensor.to_scene(p))),
+                        color,
+                    );
+                }
+                // Cross through the boresight
+                let centre = ((left + right) / 2.0, (bottom + top) / 2.0);
+                for edge in [
+                    fov.edge(range, (left, centre.1), (right, centre.1)),
+                    fov.edge(range, (centre.0, bottom), (centre.0, top)),
+                ] {
+                    gizmos.linestrip(
+                        edge.into_iter().map(|p| view.project(sensor.to_scene(p))),
+                        color,
+                    );
+                }
+            }
 
-            let positions: Vec<_> = (0..steps + 1)
-                .map(|i| {
-                    let el = (fov.el / (steps as f32)) * (i as f32) - (fov.el / 2.0);
-                    let p = PolarVec3::new(fov.range, -fov.az / 2.0, el);
-                    p.into()
-                })
-                .collect();
-            gizmos.linestrip(positions, color);
+            // Lines from the sensor to each corner, where the view has a place for the sensor
+            if !matches!(view.mode, RenderMode::Spherical | RenderMode::BScope) {
+                let start = |az, el| match fov.min_range {
+                    range if range > 0.0 => sensor.to_scene(PolarVec3::new(range, az, el)),
+                    _ => sensor.origin,
+                };
+                for (az, el) in corners {
+                    let end: Vec3 = sensor.to_scene(PolarVec3::new(fov.range, az, el));
+                    gizmos.line(view.project(start(az, el)), view.project(end), color);
+                }
+            }
         }
     }
 }
@@ -0,0 +1,137 @@
+use bevy::math::{Mat3, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::covariance::Covariance;
+use crate::state::State;
+
+/// Directions of the input x, y and z axes, which are right handed in every frame. Beam
+/// azimuths are measured from the sensor boresight towards its left, and elevations upwards.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Axes {
+    /// x forward along the sensor boresight, y to its left and z up
+    #[default]
+    Boresight,
+    /// x east, y north and z up, with the sensor looking north
+    Enu,
+    /// x north, y east and z down, with the sensor looking north
+    Ned,
+}
+
+/// Layout of position and velocity within a state vector
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StateOrder {
+    /// `[x, vx, y, vy, z, vz]`
+    #[default]
+    Interleaved,
+    /// `[x, y, z, vx, vy, vz]`
+    Grouped,
+}
+
+/// The coordinate frame that input states are given in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Frame {
+    #[serde(default)]
+    pub axes: Axes,
+    #[serde(default)]
+    pub order: StateOrder,
+}
+
+impl Frame {
+    /// Rotation from the input axes into the scene, where the sensor looks along z with y up
+    pub fn rotation(&self) -> Mat3 {
+        // Each column is where the input axis ends up in the scene. The scene is right handed
+        // too, so with the sensor looking along z and y up, x is to its left.
+        match self.axes {
+            Axes::Boresight => Mat3::from_cols(Vec3::Z, Vec3::X, Vec3::Y),
+            Axes::Enu => Mat3::from_cols(Vec3::NEG_X, Vec3::Z, Vec3::Y),
+            Axes::Ned => Mat3::from_cols(Vec3::Z, Vec3::NEG_X, Vec3::NEG_Y),
+        }
+    }
+
+    /// Index of the x, y and z position within a state vector
+    pub fn position_indices(&self) -> [usize; 3] {
+        match self.order {
+            StateOrder::Interleaved => [0, 2, 4],
+            StateOrder::Grouped => [0, 1, 2],
+        }
+    }
+
+    /// Index of the x, y and z velocity within a state vector, on the same axes as the position
+    pub fn velocity_indices(&self) -> [usize; 3] {
+        match self.order {
+            StateOrder::Interleaved => [1, 3, 5],
+            StateOrder::Grouped => [3, 4, 5],
+        }
+    }
+
+    /// Convert an input state vector into the scene
+    pub fn state(&self, v: &[f32; 6]) -> State {
+        let rotation = self.rotation();
+        State {
+            pos: rotation * Vec3::from_array(self.position_indices().map(|i| v[i])),
+            vel: rotation * Vec3::from_array(self.velocity_indices().map(|i| v[i])),
+        }
+    }
+
+    /// Extract the position covariance of an input state covariance, in the scene
+    pub fn covariance(&self, full: &[[f32; 6]; 6]) -> Covariance {
+        let rotation = self.rotation();
+        let Covariance(input) = Covariance::from_state(full, self.position_indices());
+        Covariance(rotation * input * rotation.transpose())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::math::Vec3;
+
+    use crate::polar::PolarVec3;
+
+    use super::{Axes, Frame, StateOrder};
+
+    #[test]
+    fn frames_agree() {
+        // A target 1km ahead of the sensor, 200m to its left and 100m up, moving forwards
+        let inputs = [
+            (Axes::Boresight, [1000.0, 10.0, 200.0, 0.0, 100.0, 0.0]),
+            (Axes::Enu, [-200.0, 0.0, 1000.0, 10.0, 100.0, 0.0]),
+            (Axes::Ned, [1000.0, 10.0, -200.0, 0.0, -100.0, 0.0]),
+        ];
+
+        for (axes, state) in inputs {
+            let frame = Frame {
+                axes,
+                order: StateOrder::Interleaved,
+            };
+            let state = frame.state(&state);
+            assert_eq!(state.pos, Vec3::new(200.0, 100.0, 1000.0));
+            // The velocity is along the same scene axis as the position it belongs to
+            assert_eq!(state.vel, Vec3::new(0.0, 0.0, 10.0));
+
+            let polar = PolarVec3::from(state.pos);
+            assert!(polar.azimuth > 0.0 && polar.elevation > 0.0);
+        }
+
+        let grouped = Frame {
+            axes: Axes::Boresight,
+            order: StateOrder::Grouped,
+        };
+        let state = grouped.state(&[1000.0, 200.0, 100.0, 10.0, 0.0, 0.0]);
+        assert_eq!(state.pos, Vec3::new(200.0, 100.0, 1000.0));
+        assert_eq!(state.vel, Vec3::new(0.0, 0.0, 10.0));
+    }
+
+    #[test]
+    fn rotations_are_proper() {
+        for axes in [Axes::Boresight, Axes::Enu, Axes::Ned] {
+            let frame = Frame {
+                axes,
+                ..Default::default()
+            };
+            assert_eq!(frame.rotation().determinant(), 1.0, "{:?}", axes);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use bevy::ecs::component::Component;
+use bevy::ecs::query::With;
+use bevy::ecs::system::{Commands, Query, Res, Resource};
+use bevy::math::{Vec2, Vec3};
+use bevy::render::color::Color;
+use bevy::render::view::Visibility;
+use bevy::text::{Text, TextStyle};
+use bevy::ui::node_bundles::TextBundle;
+use bevy::ui::{PositionType, Style, Val};
+use bevy::utils::default;
+
+use crate::camera::{View, ViewCameras, Views};
+use crate::polar::PolarVec3;
+use crate::sensor::{Sensor, Sensors};
+use crate::RenderMode;
+
+/// Number of segments in each drawn ring or graticule line
+const GRID_STEPS: usize = 48;
+
+/// Size of grid label text, in logical pixels
+const LABEL_SIZE: f32 = 14.0;
+
+/// Range rings, angle graticules and their labels, drawn over the first sensor's FoV
+#[derive(clap::Args, Resource, Clone, Debug)]
+pub struct GridConfig {
+    /// Start with the grid hidden, toggled with G
+    #[arg(long = "hide-grid", action = clap::ArgAction::SetFalse)]
+    pub show: bool,
+
+    /// Smallest number of range rings across the FoV
+    #[arg(long, default_value_t = 4)]
+    pub grid_rings: usize,
+
+    /// Smallest number of azimuth and elevation lines across the FoV
+    #[arg(long, default_value_t = 4)]
+    pub grid_lines: usize,
+}
+
+/// The round step, of 1, 2 or 5 times a power of ten, that splits `extent` into at least
+/// `count` parts
+fn spacing(extent: f32, count: usize) -> f32 {
+    let rough = extent / count.max(1) as f32;
+    let power = 10f32.powf(rough.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * power)
+        .take_while(|step| *step <= rough)
+        .last()
+        .unwrap_or(power)
+}
+
+/// Multiples of `step` within the limits, inclusive
+fn steps([from, to]: [f32; 2], step: f32) -> impl Iterator<Item = f32> {
+    let first = (from / step).ceil() as i32;
+    let last = (to / step).floor() as i32;
+    (first..=last).map(move |i| i as f32 * step)
+}
+
+fn range_label(range: f32) -> String {
+    format!("{} km", range / 1000.0)
+}
+
+fn angle_label(angle: f32) -> String {
+    // Adding zero turns -0 into 0
+    format!("{}°", angle.to_degrees().round() + 0.0)
+}
+
+/// Lines and labels of a grid, in the scene
+#[derive(Default)]
+struct Grid {
+    lines: Vec<Vec<Vec3>>,
+    labels: Vec<(Vec3, String)>,
+}
+
+impl Grid {
+    /// A line from one point to another relative to the sensor, evenly spaced in range and angle
+    fn sweep(&mut self, sensor: &Sensor, from: PolarVec3, to: PolarVec3) {
+        let lerp = |a: f32, b: f32, s: f32| a + (b - a) * s;
+        let line = (0..=GRID_STEPS)
+            .map(|i| {
+                let s = i as f32 / GRID_STEPS as f32;
+                sensor.to_scene(PolarVec3::new(
+                    lerp(from.range, to.range, s),
+                    lerp(from.azimuth, to.azimuth, s),
+                    lerp(from.elevation, to.elevation, s),
+                ))
+            })
+            .collect();
+        self.lines.push(line);
+    }
+
+    fn label(&mut self, sensor: &Sensor, at: PolarVec3, text: String) {
+        self.labels.push((sensor.to_scene(at), text));
+    }
+
+    /// The grid a view shows over a sensor's FoV
+    fn new(view: View, sensor: &Sensor, config: &GridConfig) -> Self {
+        let fov = &sensor.fov;
+        let [right, left] = fov.az;
+        let [bottom, top] = fov.el;
+        // The sensor itself has no direction, so lines out from it start just in front
+        let near = fov.min_range.max(1.0);
+        let ranges = steps([near, fov.range], spacing(fov.range, config.grid_rings));
+        let angles = |[from, to]: [f32; 2]| {
+            let degrees = spacing((to - from).to_degrees(), config.grid_lines).max(1.0);
+            steps([from, to], degrees.to_radians())
+        };
+        let at = PolarVec3::new;
+
+        let mut grid = Self::default();
+        match view.mode {
+            RenderMode::Ppi | RenderMode::BScope | RenderMode::Cartesian => {
+                // Range rings and azimuth spokes in the plane of the boresight
+                for range in ranges {
+                    grid.sweep(sensor, at(range, left, 0.0), at(range, right, 0.0));
+                    grid.label(sensor, at(range, right, 0.0), range_label(range));
+                }
+                for az in angles(fov.az) {
+                    grid.sweep(sensor, at(near, az, 0.0), at(fov.range, az, 0.0));
+                    grid.label(sensor, at(fov.range, az, 0.0), angle_label(az));
+                }
+            }
+            RenderMode::Rhi => {
+                // Range rings and elevation spokes through the slice of the RHI
+                let az = view.cut.azimuth_from(sensor);
+                for range in ranges {
+                    grid.sweep(sensor, at(range, az, bottom), at(range, az, top));
+                    grid.label(sensor, at(range, az, bottom), range_label(range));
+                }
+                for el in angles(fov.el) {
+                    grid.sweep(sensor, at(near, az, el), at(fov.range, az, el));
+                    grid.label(sensor, at(fov.range, az, el), angle_label(el));
+                }
+            }
+            RenderMode::Spherical => {
+                // The range axis runs along the bottom left edge of the FoV
+                grid.sweep(sensor, at(near, left, bottom), at(fov.range, left, bottom));
+                for range in ranges {
+                    grid.label(sensor, at(range, left, bottom), range_label(range));
+                }
+            }
+        }
+
+        // Azimuth and elevation graticules on the far surface of the FoV
+        if let RenderMode::Cartesian | RenderMode::Spherical = view.mode {
+            let range = fov.range;
+            for az in angles(fov.az) {
+                grid.sweep(sensor, at(range, az, bottom), at(range, az, top));
+                if let RenderMode::Spherical = view.mode {
+                    grid.label(sensor, at(range, az, bottom), angle_label(az));
+                }
+            }
+            for el in angles(fov.el) {
+                grid.sweep(sensor, at(range, left, el), at(range, right, el));
+                grid.label(sensor, at(range, left, el), angle_label(el));
+            }
+        }
+        grid
+    }
+}
+
+/// Draw the grid of every view
+pub fn render_grids(mut views: Views, config: Res<GridConfig>, sensors: Res<Sensors>) {
+    if !config.show {
+        return;
+    }
+    let color = Color::GRAY.with_a(0.5);
+    for (view, gizmos) in views.iter_mut() {
+        for line in Grid::new(view, &sensors.0[0], &config).lines {
+            gizmos.linestrip(line.into_iter().map(|p| view.project(p)), color);
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct GridLabel;
+
+/// Place grid labels over each view. They are UI text, so they stay the same size however far
+/// the view is zoomed.
+pub fn update_grid_labels(
+    mut commands: Commands,
+    config: Res<GridConfig>,
+    sensors: Res<Sensors>,
+    cameras: ViewCameras,
+    mut labels: Query<(&mut Text, &mut Style, &mut Visibility), With<GridLabel>>,
+) {
+    let mut placed = Vec::new();
+    for (camera, transform, view) in cameras.iter() {
+        if !config.show || !camera.is_active {
+            continue;
+        }
+        let Some(rect) = camera.logical_viewport_rect() else {
+            continue;
+        };
+        for (p, text) in Grid::new(view, &sensors.0[0], &config).labels {
+            let Some(position) = camera.world_to_viewport(transform, view.project(p)) else {
+                continue;
+            };
+            let position = rect.min + position;
+            // Labels that would overlap, such as along an axis seen end on, are left out
+            let overlaps = placed
+                .iter()
+                .any(|(other, _): &(Vec2, _)| other.distance(position) < LABEL_SIZE);
+            if rect.contains(position) && !overlaps {
+                placed.push((position, text));
+            }
+        }
+    }
+
+    // Labels are reused between frames, and spare ones are hidden
+    let mut placed = placed.into_iter();
+    for (mut text, mut style, mut visibility) in labels.iter_mut() {
+        match placed.next() {
+            Some((position, value)) => {
+                text.sections[0].value = value;
+                style.left = Val::Px(position.x);
+                style.top = Val::Px(position.y);
+                *visibility = Visibility::Inherited;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+    for (position, value) in placed {
+        commands.spawn((
+            TextBundle::from_section(
+                value,
+                TextStyle {
+                    font_size: LABEL_SIZE,
+                    color: Color::DARK_GRAY,
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(position.x),
+                top: Val::Px(position.y),
+                ..default()
+            }),
+            GridLabel,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{spacing, steps};
+
+    #[test]
+    fn round_spacing() {
+        assert_eq!(spacing(200_000.0, 4), 50_000.0);
+        assert_eq!(spacing(150_000.0, 4), 20_000.0);
+        assert_eq!(spacing(90.0, 4), 20.0);
+        let rings: Vec<f32> = steps([1.0, 200_000.0], 50_000.0).collect();
+        assert_eq!(rings, vec![50_000.0, 100_000.0, 150_000.0, 200_000.0]);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use bevy::ecs::component::Component;
+use bevy::ecs::query::With;
+use bevy::ecs::system::{Local, Query, Res};
+use bevy::math::Quat;
+use bevy::render::color::Color;
+use bevy::text::Text;
+
+use crate::beam::{BeamKey, BeamState};
+use crate::camera::Views;
+use crate::state::State;
+use crate::timeseries::{Active, Staleness, Time, TimeSeries};
+use crate::truth::Truth;
+
+/// Beam samples further back than this are not checked when time jumps forwards, in seconds
+const MAX_LOOKBACK: f64 = 10.0;
+
+/// Which beams are currently on a truth, and when one last was
+#[derive(Component, Clone, Debug, Default)]
+pub struct Illumination {
+    pub beams: Vec<BeamKey>,
+    pub last: Option<f64>,
+}
+
+/// Find the beams on each truth. Beam samples since the previous frame are also checked, so
+/// dwells between frames are not missed at high playback speeds. Stepping back in time forgets
+/// when truths were last illuminated.
+pub fn update_illumination(
+    time: Res<Time>,
+    staleness: Res<Staleness>,
+    mut previous: Local<Option<f64>>,
+    beams: Query<(&BeamState, &TimeSeries<BeamState>, &Active)>,
+    mut truths: Query<(&State, &TimeSeries<State>, &Active, &mut Illumination), With<Truth>>,
+) {
+    let start = match *previous {
+        Some(previous) if previous <= time.0 => previous.max(time.0 - MAX_LOOKBACK),
+        _ => {
+            for (_, _, _, mut illumination) in truths.iter_mut() {
+                illumination.last = None;
+            }
+            time.0 - MAX_LOOKBACK
+        }
+    };
+    *previous = Some(time.0);
+
+    for (state, history, active, mut illumination) in truths.iter_mut() {
+        illumination.beams.clear();
+        if !active.0 {
+            continue;
+        }
+
+        for (beam, beam_history, beam_active) in beams.iter() {
+            if beam_active.0 && beam.illuminates(state.pos) {
+                illumination.beams.push(beam.key);
+                illumination.last = Some(time.0);
+                continue;
+            }
+            let swept = beam_history
+                .range(start, time.0)
+                .iter()
+                .rev()
+                .filter(|(t, _)| *t > start)
+                .find(|(t, beam)| {
+                    history
+                        .at(*t, staleness.0)
+                        .is_some_and(|state| beam.illuminates(state.pos))
+                });
+            if let Some((t, _)) = swept {
+                illumination.last = Some(illumination.last.map_or(*t, |last| last.max(*t)));
+            }
+        }
+        illumination.beams.sort();
+    }
+}
+
+/// Ring each illuminated truth in the color of every beam on it
+pub fn render_illumination(
+    mut views: Views,
+    truths: Query<(&State, &Illumination, &Active), With<Truth>>,
+    beams: Query<&BeamState>,
+) {
+    let colors: HashMap<BeamKey, Color> = beams.iter().map(|b| (b.key, b.color())).collect();
+    for (view, gizmos) in views.iter_mut() {
+        for (state, illumination, active) in truths.iter() {
+            if !active.0 {
+                continue;
+            }
+            for (i, key) in illumination.beams.iter().enumerate() {
+                let scale = 1.0 + 0.5 * (i + 1) as f32;
+                let color = colors.get(key).copied().unwrap_or(Color::BLACK);
+                let size = view.marker_size() * scale;
+                gizmos.sphere(view.project(state.pos), Quat::default(), size, color);
+            }
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct IlluminationText;
+pub fn illumination_text_update(
+    time: Res<Time>,
+    truths: Query<(&Truth, &Illumination, &Active)>,
+    mut query: Query<&mut Text, With<IlluminationText>>,
+) {
+    let mut truths: Vec<_> = truths.iter().filter(|(_, _, active)| active.0).collect();
+    truths.sort_by(|a, b| a.0 .0.cmp(&b.0 .0));
+
+    let mut value = String::from("Truth  Beams  Since lit\n");
+    for (truth, illumination, _) in truths {
+        let beams: Vec<_> = illumination.beams.iter().map(|b| b.to_string()).collect();
+        let beams = if beams.is_empty() {
+            "-".to_string()
+        } else {
+            beams.join(",")
+        };
+        let since = match illumination.last {
+            Some(last) => format!("{:.2}s", time.0 - last),
+            None => "-".to_string(),
+        };
+        value += &format!("{}  {}  {}\n", truth.0, beams, since);
+    }
+
+    for mut text in &mut query {
+        text.sections[0].value = value.clone();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::math::{Vec2, Vec3};
+
+    use crate::beam::BeamState;
+    use crate::polar::PolarVec3;
+
+    #[test]
+    fn inside_beam() {
+        let beam = BeamState {
+            width: Vec2::new(0.2, 0.1),
+            target: PolarVec3::new(1000.0, 0.5, 0.0),
+            ..Default::default()
+        };
+        let at = |range: f32, az: f32, el: f32| Vec3::from(PolarVec3::new(range, az, el));
+
+        assert!(beam.illuminates(at(500.0, 0.5, 0.0)));
+        assert!(beam.illuminates(at(500.0, 0.45, 0.03)));
+        // Wider in azimuth than in elevation
+        assert!(beam.illuminates(at(500.0, 0.42, 0.0)));
+        assert!(!beam.illuminates(at(500.0, 0.35, 0.0)));
+        assert!(!beam.illuminates(at(500.0, 0.5, 0.07)));
+        // Past the end of the beam
+        assert!(!beam.illuminates(at(1500.0, 0.5, 0.0)));
+
+        let gated = BeamState {
+            min_range: 800.0,
+            ..beam
+        };
+        assert!(!gated.illuminates(at(500.0, 0.5, 0.0)));
+        assert!(gated.illuminates(at(900.0, 0.5, 0.0)));
+    }
+}
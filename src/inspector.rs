@@ -0,0 +1,244 @@
+use bevy::ecs::component::Component;
+use bevy::ecs::entity::Entity;
+use bevy::ecs::query::{Or, With};
+use bevy::ecs::system::{Local, Query, Res, ResMut, Resource};
+use bevy::input::keyboard::KeyCode;
+use bevy::input::mouse::MouseButton;
+use bevy::input::Input;
+use bevy::math::{Quat, Vec2};
+use bevy::render::color::Color;
+use bevy::text::Text;
+use bevy::ui::Interaction;
+use bevy::window::{PrimaryWindow, Window};
+
+use crate::beam::BeamState;
+use crate::camera::{ViewCameras, Views};
+use crate::sensor::{Sensor, Sensors};
+use crate::state::State;
+use crate::timeseries::{Active, TimeSeries};
+use crate::track::Track;
+use crate::truth::Truth;
+
+/// How close a click must be to an entity to select it, in logical pixels
+const PICK_RADIUS: f32 = 12.0;
+
+/// How far the cursor may move between pressing and releasing the button for it to still be a
+/// click, rather than a drag of the camera, in logical pixels
+const CLICK_DISTANCE: f32 = 4.0;
+
+/// The entity shown in the inspector, if any
+#[derive(Resource, Debug, Default)]
+pub struct Selected(pub Option<Entity>);
+
+/// Truths and tracks, which are picked by their position
+type Targets = Or<(With<Truth>, With<Track>)>;
+
+/// The candidate closest to the cursor, if any is within the pick radius
+fn nearest(cursor: Vec2, candidates: impl Iterator<Item = (Entity, Vec2)>) -> Option<Entity> {
+    candidates
+        .map(|(entity, position)| (entity, position.distance(cursor)))
+        .filter(|(_, distance)| *distance <= PICK_RADIUS)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(entity, _)| entity)
+}
+
+/// Select the truth, track or beam under the cursor when the left button is clicked, in
+/// whichever view the cursor is over. Presses on the UI and drags of the camera are not clicks.
+/// Escape clears the selection.
+#[allow(clippy::too_many_arguments)]
+pub fn pick_entities(
+    mouse: Res<Input<MouseButton>>,
+    keycode: Res<Input<KeyCode>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    interactions: Query<&Interaction>,
+    cameras: ViewCameras,
+    states: Query<(Entity, &State, &Active), Targets>,
+    beams: Query<(Entity, &BeamState, &Active)>,
+    mut selected: ResMut<Selected>,
+    mut pressed: Local<Option<Vec2>>,
+) {
+    if keycode.just_pressed(KeyCode::Escape) {
+        selected.0 = None;
+    }
+    let Some(cursor) = windows.get_single().ok().and_then(|w| w.cursor_position()) else {
+        return;
+    };
+    if mouse.just_pressed(MouseButton::Left) {
+        let over_ui = interactions.iter().any(|i| *i != Interaction::None);
+        *pressed = (!over_ui).then_some(cursor);
+    }
+    if !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some(start) = pressed.take() else {
+        return;
+    };
+    if start.distance(cursor) > CLICK_DISTANCE {
+        return;
+    }
+    let under_cursor = cameras.iter().find(|(camera, ..)| {
+        camera.is_active
+            && camera
+                .logical_viewport_rect()
+                .is_some_and(|rect| rect.contains(cursor))
+    });
+    let Some((camera, transform, view)) = under_cursor else {
+        return;
+    };
+    let offset = camera.logical_viewport_rect().unwrap().min;
+
+    // Beams are picked by the far end of their center
+    let positions = states
+        .iter()
+        .filter(|(_, _, active)| active.0)
+        .map(|(entity, state, _)| (entity, state.pos))
+        .chain(
+            beams
+                .iter()
+                .filter(|(_, _, active)| active.0)
+                .map(|(entity, beam, _)| (entity, beam.center(beam.target.range))),
+        );
+    let candidates = positions.filter_map(|(entity, p)| {
+        let position = camera.world_to_viewport(transform, view.project(p))?;
+        Some((entity, offset + position))
+    });
+    if let Some(entity) = nearest(cursor, candidates) {
+        selected.0 = Some(entity);
+    }
+}
+
+/// Ring the selected entity in every view
+pub fn render_selection(
+    mut views: Views,
+    selected: Res<Selected>,
+    states: Query<&State>,
+    beams: Query<&BeamState>,
+) {
+    let Some(entity) = selected.0 else {
+        return;
+    };
+    let position = match (states.get(entity), beams.get(entity)) {
+        (Ok(state), _) => state.pos,
+        (_, Ok(beam)) => beam.center(beam.target.range),
+        _ => return,
+    };
+    for (view, gizmos) in views.iter_mut() {
+        let size = view.marker_size() * 3.0;
+        gizmos.sphere(view.project(position), Quat::default(), size, Color::ORANGE);
+    }
+}
+
+fn span<T: Clone + Component>(history: &TimeSeries<T>) -> String {
+    match history.span() {
+        Some((first, last)) => format!("{:.2}s to {:.2}s", first, last),
+        None => "-".to_string(),
+    }
+}
+
+/// Position and velocity in the scene, and where a state is from the sensor's boresight
+fn describe_state(state: &State, sensor: &Sensor) -> String {
+    let polar = sensor.to_sensor(state.pos);
+    let (pos, vel) = (state.pos, state.vel);
+    // Heading is measured like azimuth, in the plane of the sensor's boresight
+    let relative = sensor.rotation.inverse() * vel;
+    let heading = relative.x.atan2(relative.z);
+    format!(
+        "Scene position  {:.0}, {:.0}, {:.0} m\nScene velocity  {:.1}, {:.1}, {:.1} m/s\nSensor  {}\nRange  {:.2} km\nAzimuth  {:.2}°\nElevation  {:.2}°\nSpeed  {:.1} m/s\nHeading  {:.1}°\n",
+        pos.x,
+        pos.y,
+        pos.z,
+        vel.x,
+        vel.y,
+        vel.z,
+        sensor.name,
+        polar.range / 1000.0,
+        polar.azimuth.to_degrees(),
+        polar.elevation.to_degrees(),
+        vel.length(),
+        heading.to_degrees(),
+    )
+}
+
+#[derive(Component)]
+pub struct InspectorText;
+pub fn inspector_text_update(
+    mut selected: ResMut<Selected>,
+    sensors: Res<Sensors>,
+    truths: Query<(&Truth, &State, &TimeSeries<State>)>,
+    tracks: Query<(&Track, &State, &TimeSeries<State>)>,
+    beams: Query<(&BeamState, &TimeSeries<BeamState>)>,
+    mut query: Query<&mut Text, With<InspectorText>>,
+) {
+    // Truths and tracks are described from the first sensor, and beams from their own
+    let first = &sensors.0[0];
+    let value = match selected.0 {
+        None => String::new(),
+        Some(entity) => {
+            if let Ok((truth, state, history)) = truths.get(entity) {
+                format!(
+                    "Truth {}\n{}Samples  {}\n",
+                    truth.0,
+                    describe_state(state, first),
+                    span(history)
+                )
+            } else if let Ok((track, state, history)) = tracks.get(entity) {
+                format!(
+                    "Track {}\n{}Samples  {}\n",
+                    track.0,
+                    describe_state(state, first),
+                    span(history)
+                )
+            } else if let Ok((beam, history)) = beams.get(entity) {
+                format!(
+                    "Beam {}\nSensor  {}\nWaveform  {}\nAzimuth  {:.2}°\nElevation  {:.2}°\nWidth  {:.2}° x {:.2}°\nGate  {:.1}-{:.1} km\nSamples  {}\n",
+                    beam.key,
+                    sensors.0.get(beam.sensor).map_or("-", |s| s.name.as_str()),
+                    beam.waveform.as_deref().unwrap_or("-"),
+                    beam.target.azimuth.to_degrees(),
+                    beam.target.elevation.to_degrees(),
+                    beam.width.x.to_degrees(),
+                    beam.width.y.to_degrees(),
+                    beam.min_range / 1000.0,
+                    beam.target.range / 1000.0,
+                    span(history)
+                )
+            } else {
+                // The entity has gone, such as when another run is opened
+                selected.0 = None;
+                String::new()
+            }
+        }
+    };
+
+    for mut text in &mut query {
+        text.sections[0].value = value.clone();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::entity::Entity;
+    use bevy::math::Vec2;
+
+    use super::nearest;
+
+    #[test]
+    fn picks_closest() {
+        let (a, b) = (Entity::from_raw(1), Entity::from_raw(2));
+        let candidates = || [(a, Vec2::new(100.0, 100.0)), (b, Vec2::new(108.0, 100.0))];
+
+        assert_eq!(
+            nearest(Vec2::new(102.0, 100.0), candidates().into_iter()),
+            Some(a)
+        );
+        assert_eq!(
+            nearest(Vec2::new(107.0, 101.0), candidates().into_iter()),
+            Some(b)
+        );
+        // Too far from either
+        assert_eq!(
+            nearest(Vec2::new(100.0, 130.0), candidates().into_iter()),
+            None
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use std::f32::consts::TAU;
+
+use bevy::asset::{Assets, Handle};
+use bevy::ecs::component::Component;
+use bevy::ecs::system::{Commands, Query, ResMut, Resource};
+use bevy::math::{Quat, Vec3};
+use bevy::pbr::{AlphaMode, PbrBundle, StandardMaterial};
+use bevy::render::color::Color;
+use bevy::render::mesh::{Mesh, PrimitiveTopology};
+use bevy::render::view::{NoFrustumCulling, RenderLayers, Visibility};
+use bevy::transform::components::Transform;
+use bevy::utils::default;
+
+/// Number of segments in each circle of a sphere
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// The render layer of the view with the given index. Layer 0 is left empty and layer 1 is the
+/// UI, so no camera sees the lines of another view.
+pub fn view_layer(index: usize) -> RenderLayers {
+    RenderLayers::layer(2 + index as u8)
+}
+
+/// Lines drawn into a single view this frame. Gizmos are seen by every camera, so each view's
+/// lines are drawn as a mesh on the render layer of its own camera instead.
+#[derive(Default)]
+pub struct Lines {
+    positions: Vec<[f32; 3]>,
+    colors: Vec<[f32; 4]>,
+}
+
+impl Lines {
+    /// A line between two points. Lines to points the view leaves out, which are NaN, are
+    /// skipped.
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: Color) {
+        if !start.is_finite() || !end.is_finite() {
+            return;
+        }
+        let color = color.as_linear_rgba_f32();
+        self.positions.extend([start.to_array(), end.to_array()]);
+        self.colors.extend([color, color]);
+    }
+
+    pub fn linestrip(&mut self, points: impl IntoIterator<Item = Vec3>, color: Color) {
+        let mut points = points.into_iter();
+        let Some(mut previous) = points.next() else {
+            return;
+        };
+        for point in points {
+            self.line(previous, point, color);
+            previous = point;
+        }
+    }
+
+    pub fn circle(&mut self, position: Vec3, normal: Vec3, radius: f32, color: Color) {
+        let rotation = Quat::from_rotation_arc(Vec3::Z, normal.normalize());
+        let points = (0..=CIRCLE_SEGMENTS).map(|i| {
+            let angle = i as f32 * TAU / CIRCLE_SEGMENTS as f32;
+            position + rotation * Vec3::new(angle.cos(), angle.sin(), 0.0) * radius
+        });
+        self.linestrip(points, color);
+    }
+
+    /// A wireframe sphere, made of a circle around each of its axes
+    pub fn sphere(&mut self, position: Vec3, rotation: Quat, radius: f32, color: Color) {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            self.circle(position, rotation * axis, radius, color);
+        }
+    }
+
+    /// A wireframe unit cube, transformed into place
+    pub fn cuboid(&mut self, transform: Transform, color: Color) {
+        let corner = |x: f32, y: f32, z: f32| transform.transform_point(Vec3::new(x, y, z) / 2.0);
+        let face = |z: f32| {
+            [(-1.0, 1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)].map(|(x, y)| corner(x, y, z))
+        };
+        let (front, back) = (face(1.0), face(-1.0));
+        for i in 0..4 {
+            self.line(front[i], front[(i + 1) % 4], color);
+            self.line(back[i], back[(i + 1) % 4], color);
+            self.line(front[i], back[i], color);
+        }
+    }
+}
+
+/// Lines drawn into each view this frame, by the index of its camera
+#[derive(Resource, Default)]
+pub struct ViewLines(pub Vec<Lines>);
+
+/// The mesh that shows the lines of the view with the given index
+#[derive(Component)]
+pub struct ViewLineMesh(pub usize);
+
+/// Spawn the mesh that shows a view's lines, on that view's render layer
+pub fn spawn_view_lines(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    index: usize,
+) {
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(Mesh::new(PrimitiveTopology::LineList)),
+            material: materials.add(StandardMaterial {
+                unlit: true,
+                alpha_mode: AlphaMode::Blend,
+                ..default()
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        // The bounds are only found when the mesh is first added, but the lines change every
+        // frame
+        NoFrustumCulling,
+        view_layer(index),
+        ViewLineMesh(index),
+    ));
+}
+
+/// Move the lines drawn into each view this frame into its mesh
+pub fn update_view_lines(
+    mut lines: ResMut<ViewLines>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut query: Query<(&ViewLineMesh, &Handle<Mesh>, &mut Visibility)>,
+) {
+    for (view, handle, mut visibility) in query.iter_mut() {
+        let drawn = lines
+            .0
+            .get_mut(view.0)
+            .map(std::mem::take)
+            .unwrap_or_default();
+        let Some(mesh) = meshes.get_mut(handle) else {
+            continue;
+        };
+        *visibility = if drawn.positions.is_empty() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, drawn.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, drawn.colors);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::math::Vec3;
+    use bevy::render::color::Color;
+    use bevy::transform::components::Transform;
+
+    use super::Lines;
+
+    #[test]
+    fn strips_become_segments() {
+        let mut lines = Lines::default();
+        lines.linestrip([Vec3::ZERO, Vec3::X, Vec3::Y], Color::BLACK);
+        assert_eq!(
+            lines.positions,
+            vec![[0.0; 3], [1.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+        assert_eq!(lines.colors.len(), lines.positions.len());
+
+        let mut lines = Lines::default();
+        lines.cuboid(Transform::from_scale(Vec3::splat(2.0)), Color::BLACK);
+        assert_eq!(lines.positions.len(), 12 * 2);
+        assert!(lines
+            .positions
+            .iter()
+            .all(|p| p.iter().all(|c| c.abs() == 1.0)));
+    }
+}
@@ -0,0 +1,320 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::TcpListener;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bevy::ecs::system::{Commands, Res, ResMut, Resource};
+use bevy::log::{info, warn};
+
+use crate::association::Associator;
+use crate::cli::Args;
+use crate::data::{Header, Histories, Line};
+use crate::metrics::{MetricSample, RunMetrics};
+use crate::sensor::{Sensor, Sensors};
+use crate::timeseries::{Time, TimeFlow};
+
+/// Where live steps are read from
+#[derive(Debug, Clone)]
+pub enum Source {
+    Tcp(String),
+    Unix(PathBuf),
+    Stdin,
+    /// A file that is still being written
+    Follow(PathBuf),
+}
+
+/// How long to wait before checking a followed file for new lines
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Steps received from a running simulator, one JSON step per line
+#[derive(Resource)]
+pub struct LiveFeed {
+    lines: Mutex<Receiver<Line>>,
+    associator: Associator,
+    /// Frame and sensors of the received steps, replaced if the simulator sends a header
+    pub header: Header,
+    sensors: Vec<Sensor>,
+    /// Elapsed time of the most recently received step
+    pub latest: Option<f64>,
+}
+
+impl LiveFeed {
+    fn new(lines: Receiver<Line>) -> Self {
+        Self {
+            lines: Mutex::new(lines),
+            associator: Associator::default(),
+            header: Header::default(),
+            sensors: Header::default().sensors(),
+            latest: None,
+        }
+    }
+
+    /// Interpret following steps with a new header
+    pub fn set_header(&mut self, header: Header) {
+        self.sensors = header.sensors();
+        self.header = header;
+    }
+
+    pub fn listen(source: &Source) -> Result<Self> {
+        match source {
+            Source::Tcp(address) => {
+                let listener = TcpListener::bind(address)
+                    .with_context(|| format!("failed to listen on {}", address))?;
+                Ok(Self::from_tcp(listener))
+            }
+            Source::Unix(path) => {
+                remove_stale_socket(path)?;
+                let listener = UnixListener::bind(path)
+                    .with_context(|| format!("failed to listen on {}", path.display()))?;
+                info!("Listening for steps on {}", path.display());
+                let (sender, receiver) = channel();
+                thread::spawn(move || serve(listener.incoming(), sender));
+                Ok(Self::new(receiver))
+            }
+            Source::Stdin => {
+                let (sender, receiver) = channel();
+                thread::spawn(move || read_steps(io::stdin().lock(), &sender));
+                Ok(Self::new(receiver))
+            }
+            Source::Follow(path) => {
+                let file = File::open(path)
+                    .with_context(|| format!("failed to open {}", path.display()))?;
+                let (sender, receiver) = channel();
+                thread::spawn(move || follow(BufReader::new(file), &sender));
+                Ok(Self::new(receiver))
+            }
+        }
+    }
+
+    pub fn from_tcp(listener: TcpListener) -> Self {
+        if let Ok(address) = listener.local_addr() {
+            info!("Listening for steps on {}", address);
+        }
+        let (sender, receiver) = channel();
+        thread::spawn(move || serve(listener.incoming(), sender));
+        Self::new(receiver)
+    }
+}
+
+/// Remove the socket left behind by a previous run at the given path, so it can be bound again.
+/// Anything else there, or a socket something is still listening on, is left alone.
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if metadata.file_type().is_socket() && UnixStream::connect(path).is_err() {
+        fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Read steps from each connection in turn
+fn serve<S: Read>(incoming: impl Iterator<Item = io::Result<S>>, sender: Sender<Line>) {
+    for stream in incoming {
+        match stream {
+            Ok(stream) => read_steps(BufReader::new(stream), &sender),
+            Err(e) => warn!("failed to accept connection: {}", e),
+        }
+    }
+}
+
+/// Forward every line read until the reader closes, skipping lines that are not valid
+fn read_steps(reader: impl BufRead, sender: &Sender<Line>) {
+    for (index, line) in reader.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("failed to read step: {}", e);
+                return;
+            }
+        };
+        if !send_line(&line, index + 1, sender) {
+            return;
+        }
+    }
+}
+
+/// Forward every line appended to a file, polling for more once the end is reached
+fn follow(mut reader: impl BufRead, sender: &Sender<Line>) {
+    let mut line = String::new();
+    let mut number = 0;
+    loop {
+        match reader.read_line(&mut line) {
+            Ok(0) => thread::sleep(POLL_INTERVAL),
+            // The rest of a partially written line is read on a later poll
+            Ok(_) if !line.ends_with('\n') => continue,
+            Ok(_) => {
+                number += 1;
+                if !send_line(&line, number, sender) {
+                    return;
+                }
+                line.clear();
+            }
+            Err(e) => {
+                warn!("failed to read step: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Parse and forward a single line, returning false once the receiver is gone
+fn send_line(line: &str, number: usize, sender: &Sender<Line>) -> bool {
+    if line.trim().is_empty() {
+        return true;
+    }
+    match Line::parse(line) {
+        Ok(line) => sender.send(line).is_ok(),
+        Err(e) => {
+            warn!("line {}: {:#}", number, e);
+            true
+        }
+    }
+}
+
+/// Append newly received steps to the entity histories, spawning entities for unseen ids
+pub fn receive_live_steps(
+    mut commands: Commands,
+    feed: Option<ResMut<LiveFeed>>,
+    args: Res<Args>,
+    metrics: Option<ResMut<RunMetrics>>,
+    mut histories: Histories,
+) {
+    let Some(mut feed) = feed else {
+        return;
+    };
+    let lines: Vec<Line> = feed.lines.lock().unwrap().try_iter().collect();
+    let mut steps = Vec::with_capacity(lines.len());
+    for line in lines {
+        match line {
+            Line::Header(header) if feed.latest.is_none() && steps.is_empty() => {
+                feed.set_header(args.header(header));
+                commands.insert_resource(Sensors(feed.sensors.clone()));
+            }
+            Line::Header(_) => warn!("header received after steps, ignoring it"),
+            Line::Step(step) => steps.push(step),
+        }
+    }
+    let Some(last) = steps.last() else {
+        return;
+    };
+    feed.latest = Some(last.elapsed);
+
+    let frame = feed.header.frame;
+    if let Some(mut metrics) = metrics {
+        for step in steps.iter() {
+            metrics
+                .samples
+                .push(MetricSample::new(step, &frame, &args.metrics));
+        }
+    }
+
+    let LiveFeed {
+        associator,
+        sensors,
+        ..
+    } = &mut *feed;
+    histories.add(steps, &frame, sensors, associator, &args.association);
+}
+
+/// Keep the current time at the latest received step, if enabled
+pub fn pin_latest_time(feed: Option<Res<LiveFeed>>, flow: Res<TimeFlow>, mut time: ResMut<Time>) {
+    if !flow.pin_latest {
+        return;
+    }
+    if let Some(latest) = feed.and_then(|feed| feed.latest) {
+        time.0 = latest;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::os::unix::net::UnixListener;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::data::{Line, Step};
+
+    use super::{follow, LiveFeed, Source};
+
+    fn step(line: Line) -> Step {
+        match line {
+            Line::Step(step) => step,
+            Line::Header(_) => panic!("expected a step"),
+        }
+    }
+
+    #[test]
+    fn tcp_feed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let feed = LiveFeed::from_tcp(listener);
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        writeln!(
+            stream,
+            r#"{{"elapsed": 0.5, "truths": {{"a": [1, 0, 2, 0, 3, 0]}}, "tracks": {{}}, "beams": []}}"#
+        )
+        .unwrap();
+        writeln!(stream, "not a step").unwrap();
+        writeln!(
+            stream,
+            r#"{{"elapsed": 1.0, "truths": {{}}, "tracks": {{}}, "beams": []}}"#
+        )
+        .unwrap();
+
+        let lines = feed.lines.lock().unwrap();
+        let first = step(lines.recv_timeout(Duration::from_secs(5)).unwrap());
+        assert_eq!(first.elapsed, 0.5);
+        assert!(first.truths.contains_key("a"));
+        let second = step(lines.recv_timeout(Duration::from_secs(5)).unwrap());
+        assert_eq!(second.elapsed, 1.0);
+    }
+
+    #[test]
+    fn stale_socket() {
+        let path = std::env::temp_dir().join(format!("radar-view-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // A socket left behind by a listener that has gone is replaced
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(LiveFeed::listen(&Source::Unix(path.clone())).is_ok());
+
+        // One that is still listened on is not
+        assert!(LiveFeed::listen(&Source::Unix(path.clone())).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        // Nor is anything other than a socket
+        std::fs::write(&path, "not a socket").unwrap();
+        assert!(LiveFeed::listen(&Source::Unix(path.clone())).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn follow_partial_lines() {
+        // A reader that ends partway through a line, as a file being written would
+        let written = concat!(
+            r#"{"elapsed": 0.5, "truths": {}, "tracks": {}, "beams": []}"#,
+            "\n",
+            r#"{"elapsed": 1.0, "truths": {"#,
+        );
+        let (sender, receiver) = channel();
+        thread::spawn(move || follow(Cursor::new(written), &sender));
+
+        let first = step(receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+        assert_eq!(first.elapsed, 0.5);
+        assert!(receiver.recv_timeout(Duration::from_millis(300)).is_err());
+    }
+}
@@ -1,105 +1,322 @@
+mod association;
 mod beam;
+mod binary;
+mod camera;
+mod cli;
+mod covariance;
+mod coverage;
 mod data;
 mod fov;
+mod frame;
+mod grid;
+mod illumination;
+mod inspector;
+mod lines;
+mod live;
+mod mapped;
+mod metrics;
 mod polar;
+mod sensor;
 mod state;
 mod timeseries;
+mod track;
 mod truth;
 mod ui;
+mod validate;
 
-use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+use std::f32::consts::PI;
 
-use bevy::{prelude::*, render::camera::ScalingMode};
+use anyhow::Result;
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::*,
+    render::{camera::ScalingMode, view::RenderLayers},
+};
 use bevy_panorbit_camera::{PanOrbitCamera, PanOrbitCameraPlugin};
+use clap::Parser;
 
-use beam::BeamState;
+use association::{Association, AssociationText};
+use beam::{BeamState, BeamText};
+use camera::{SceneCamera, SplitScreen};
+use cli::Args;
+use covariance::Covariance;
+use coverage::Coverage;
 use data::SimulationRun;
-use fov::FoV;
+use illumination::IlluminationText;
+use inspector::{InspectorText, Selected};
+use live::LiveFeed;
+use mapped::{LazyRun, MappedRun};
 use timeseries::ElapsedText;
-use ui::TimeControlText;
+use ui::{RenderModeButton, TimeControlText};
 
-#[derive(Resource)]
+/// Meters drawn per radian of azimuth across a B-scope
+const B_SCOPE_WIDTH: f32 = 100_000.0;
+
+/// How a camera projects the scene
+#[derive(Component, Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
 pub enum RenderMode {
     Spherical,
     Cartesian,
+    /// Plan position indicator, with range out from the sensor at the bottom and azimuth
+    /// increasing to the left of straight up
+    Ppi,
+    /// Azimuth across, increasing to the left, and range up
+    BScope,
+    /// Range height indicator through the slice given by the RHI cut, with ground range across
+    /// and height up
+    Rhi,
 }
 
-fn main() {
-    App::new()
-        .insert_resource(ClearColor(Color::WHITE))
+impl RenderMode {
+    pub fn next(self) -> Self {
+        match self {
+            RenderMode::Spherical => RenderMode::Cartesian,
+            RenderMode::Cartesian => RenderMode::Ppi,
+            RenderMode::Ppi => RenderMode::BScope,
+            RenderMode::BScope => RenderMode::Rhi,
+            RenderMode::Rhi => RenderMode::Spherical,
+        }
+    }
+
+    /// Where a point in the scene is drawn
+    pub fn project(&self, p: Vec3) -> Vec3 {
+        let polar = polar::PolarVec3::from(p);
+        let ground = polar.range * polar.elevation.cos();
+        // Cameras look along +Z with Y up, so +X, where azimuth increases, is on the left of
+        // the screen in every view
+        let screen = |left: f32, up: f32| Vec3::new(left, up, 0.0);
+        match self {
+            RenderMode::Cartesian => p,
+            RenderMode::Spherical => polar.direct_vec3(),
+            RenderMode::Ppi => screen(ground * polar.azimuth.sin(), ground * polar.azimuth.cos()),
+            RenderMode::BScope => screen(polar.azimuth * B_SCOPE_WIDTH, polar.range),
+            RenderMode::Rhi => screen(-ground, polar.range * polar.elevation.sin()),
+        }
+    }
+
+    /// Radius of the marker drawn at a truth or track
+    pub fn marker_size(&self) -> f32 {
+        match self {
+            RenderMode::Spherical => 0.006,
+            _ => 1000.0,
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if let Some(command) = &args.command {
+        return command.run(args.skip_invalid);
+    }
+
+    let mut app = App::new();
+    let sim = match args.live_source() {
+        Some(source) => {
+            let mut feed = LiveFeed::listen(&source)?;
+            feed.set_header(args.header(feed.header.clone()));
+            let sim = SimulationRun {
+                header: feed.header.clone(),
+                ..default()
+            };
+            app.insert_resource(feed);
+            sim
+        }
+        None => {
+            let path = args
+                .path
+                .as_ref()
+                .expect("path is required without a live source");
+            if args.lazy {
+                let mut run = MappedRun::open(path)?;
+                run.header = args.header(run.header);
+                let sim = SimulationRun {
+                    header: run.header.clone(),
+                    ..default()
+                };
+                let lazy = LazyRun::new(run, args.window);
+                app.insert_resource(lazy.metrics(&args.metrics));
+                app.insert_resource(lazy);
+                sim
+            } else {
+                let mut sim = SimulationRun::new(path, args.skip_invalid)?;
+                sim.header = args.header(sim.header);
+                sim
+            }
+        }
+    };
+
+    app.insert_resource(ClearColor(Color::WHITE))
+        .insert_resource(args.metrics.clone())
+        .insert_resource(args.association)
+        .insert_resource(args.grid.clone())
+        .insert_resource(args.rhi)
+        .insert_resource(args)
+        .insert_resource(sim)
         .add_plugins(DefaultPlugins)
         .add_plugins(PanOrbitCameraPlugin)
+        .init_resource::<lines::ViewLines>()
+        .init_resource::<data::OpenDialog>()
         .add_systems(Startup, setup)
         .add_systems(Update, ui::time_control)
+        .add_systems(Update, (data::open_run_dialog, data::load_opened_runs))
+        .add_systems(Update, live::receive_live_steps)
+        .add_systems(Update, mapped::update_lazy_window)
+        .add_systems(
+            Update,
+            live::pin_latest_time.after(timeseries::advance_time),
+        )
         .add_systems(Update, state::render_states)
         .add_systems(Update, beam::render_beams)
+        .add_systems(Update, beam::beam_text_update)
         .add_systems(Update, fov::render_fov)
+        .add_systems(Update, grid::render_grids)
+        .add_systems(Update, grid::update_grid_labels)
+        .add_systems(Update, ui::grid_control)
+        .add_systems(Update, ui::rhi_control)
+        .add_systems(Update, inspector::pick_entities)
+        .add_systems(Update, inspector::render_selection)
+        .add_systems(
+            Update,
+            inspector::inspector_text_update.after(inspector::pick_entities),
+        )
+        .add_systems(Update, ui::coverage_control)
+        .add_systems(Update, ui::render_mode_control)
+        .add_systems(Update, camera::layout_views.after(ui::render_mode_control))
+        .add_systems(
+            Update,
+            camera::retarget_camera.after(ui::render_mode_control),
+        )
+        .add_systems(
+            Update,
+            coverage::update_coverage.after(timeseries::advance_time),
+        )
+        .add_systems(
+            Update,
+            coverage::render_coverage.after(coverage::update_coverage),
+        )
         .add_systems(Update, timeseries::update_current_time::<BeamState>)
         .add_systems(Update, timeseries::update_current_time::<state::State>)
+        .add_systems(Update, timeseries::update_current_time::<Covariance>)
+        .add_systems(Update, timeseries::update_current_time::<Association>)
+        .add_systems(Update, association::association_text_update)
+        .add_systems(
+            Update,
+            illumination::update_illumination
+                .after(timeseries::update_current_time::<BeamState>)
+                .after(timeseries::update_current_time::<state::State>),
+        )
+        .add_systems(
+            Update,
+            illumination::render_illumination.after(illumination::update_illumination),
+        )
+        .add_systems(
+            Update,
+            illumination::illumination_text_update.after(illumination::update_illumination),
+        )
+        .add_systems(Update, metrics::compute_metrics)
+        .add_systems(Update, metrics::update_metric_plot)
+        .add_systems(Update, metrics::update_metric_cursor)
         .add_systems(Update, timeseries::advance_time)
         .add_systems(Update, timeseries::elapsed_text_update)
         .add_systems(Update, state::render_history)
+        .add_systems(Update, track::render_tracks)
+        .add_systems(Update, track::render_track_history)
+        .add_systems(Update, track::render_covariance)
+        .add_systems(Update, association::render_associations)
+        .add_systems(PostUpdate, lines::update_view_lines)
         .run();
-}
 
-#[derive(Component)]
-struct Ground;
+    Ok(())
+}
 
 //fn setup(mut commands: Commands) {
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    args: Res<Args>,
+    sim: Res<SimulationRun>,
 ) {
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(shape::Plane::from_size(20.).into()),
-            material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
-            ..default()
-        },
-        Ground,
-    ));
+    // Scene cameras, the second only shown when the screen is split. Each sees only the lines
+    // drawn into its own view.
+    for (index, mode) in [args.mode, args.mode.next()].into_iter().enumerate() {
+        lines::spawn_view_lines(&mut commands, &mut meshes, &mut materials, index);
+        commands.spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    order: if index == 0 || args.split {
+                        index as isize
+                    } else {
+                        -1
+                    },
+                    is_active: index == 0 || args.split,
+                    ..default()
+                },
+                //projection: Projection::Perspective(PerspectiveProjection {
+                //fov: FRAC_PI_2,
+                //aspect_ratio: 1.0,
+                //near: 0.0,
+                //far: 250000.0,
+                //}),
+                projection: Projection::Orthographic(OrthographicProjection {
+                    near: -300000.0,
+                    far: 300000.0,
+                    scale: 150000.0,
+                    scaling_mode: ScalingMode::FixedVertical(2.0),
+                    ..default()
+                }),
+                transform: Transform::from_xyz(100_000.0, 0.0, 0.0).looking_to(Vec3::Z, Vec3::Y),
+                //transform: Transform::from_xyz(0.0, 0.0, 100_000.0).looking_to(Vec3::Z, Vec3::Y),
+                //transform: Transform::from_xyz(15.0, 5.0, 15.0).looking_at(Vec3::ZERO, Vec3::Y),
+                //transform: Transform::from_xyz(75000.0, -50000.0, 0.0).looking_to(Vec3::Y, Vec3::Z),
+                //transform: Transform::from_xyz(75000.0, 0.0, 0.0).looking_to(Vec3::NEG_Z, Vec3::X),
+                ..default()
+            },
+            PanOrbitCamera {
+                alpha: Some(PI),
+                focus: Vec3::Z * 100_100.0,
+                ..default()
+            },
+            mode,
+            SceneCamera(index),
+            lines::view_layer(index),
+            UiCameraConfig { show_ui: false },
+        ));
+    }
 
-    // camera
+    // The UI is drawn once over the whole window, whatever the layout of the scene cameras.
+    // It is kept off the render layers of the views, so it does not draw the scene again.
     commands.spawn((
-        Camera3dBundle {
-            //projection: Projection::Perspective(PerspectiveProjection {
-            //fov: FRAC_PI_2,
-            //aspect_ratio: 1.0,
-            //near: 0.0,
-            //far: 250000.0,
-            //}),
-            projection: Projection::Orthographic(OrthographicProjection {
-                near: -300000.0,
-                far: 300000.0,
-                //scale: 1.2,
-                scale: 150000.0,
-                scaling_mode: ScalingMode::FixedVertical(2.0),
+        Camera2dBundle {
+            camera: Camera {
+                order: 2,
                 ..default()
-            }),
-            transform: Transform::from_xyz(100_000.0, 0.0, 0.0).looking_to(Vec3::Z, Vec3::Y),
-            //transform: Transform::from_xyz(0.0, 0.0, 100_000.0).looking_to(Vec3::Z, Vec3::Y),
-            //transform: Transform::from_xyz(15.0, 5.0, 15.0).looking_at(Vec3::ZERO, Vec3::Y),
-            //transform: Transform::from_xyz(75000.0, -50000.0, 0.0).looking_to(Vec3::Y, Vec3::Z),
-            //transform: Transform::from_xyz(75000.0, 0.0, 0.0).looking_to(Vec3::NEG_Z, Vec3::X),
-            ..default()
-        },
-        PanOrbitCamera {
-            alpha: Some(PI),
-            focus: Vec3::Z * 100_100.0,
+            },
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::None,
+            },
             ..default()
         },
+        RenderLayers::layer(1),
     ));
 
-    //commands.insert_resource(RenderMode::Spherical);
-    commands.insert_resource(RenderMode::Cartesian);
-    commands.insert_resource(timeseries::Time(0.0));
-    commands.insert_resource(timeseries::TimeFlow::default());
-    commands.insert_resource(FoV::default());
+    commands.insert_resource(SplitScreen(args.split));
+    commands.insert_resource(Selected::default());
+    commands.insert_resource(timeseries::Time(args.start));
+    commands.insert_resource(timeseries::StartTime(args.start));
+    commands.insert_resource(timeseries::Staleness(args.staleness));
+    commands.insert_resource(timeseries::TimeFlow {
+        delta: args.speed,
+        pin_latest: args.pin_latest,
+        ..default()
+    });
+    commands.insert_resource(Coverage::new(
+        args.coverage,
+        args.coverage_window,
+        args.coverage_cell.to_radians(),
+    ));
 
-    let sim = SimulationRun::new("./sim_3482576718.json").unwrap();
-    commands.spawn_batch(sim.truths());
-    commands.spawn_batch(sim.beams());
+    sim.spawn(&mut commands, &args.association);
 
     commands.spawn((
         TextBundle::from_section(
@@ -120,7 +337,7 @@ fn setup(
     commands.spawn((
         TextBundle::from_sections([
             TextSection::new(
-                "Space: Pause/Resume\n</>: change speed\nLeft/Right: Single step\nR: Restart\n",
+                "Space: Pause/Resume\n</>: change speed\nLeft/Right: Single step\nR: Restart\nL: Pin to latest live step\nH: Cycle beam coverage heatmap\nM: Cycle view between Cartesian, PPI, B-scope, RHI and Spherical\nV: Split the screen between two views\n[/]: Turn the RHI slice in azimuth\nG: Show/hide the grid\nClick: Inspect a truth, track or beam\nEsc: Clear the inspector\nO: Open a run file, or drop one onto the window\n",
                 TextStyle {
                     color: Color::BLACK,
                     ..default()
@@ -142,4 +359,88 @@ fn setup(
         }),
         TimeControlText,
     ));
+    commands.spawn((
+        TextBundle::from_section(
+            "Associations",
+            TextStyle {
+                color: Color::BLACK,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            right: Val::Px(5.0),
+            ..default()
+        }),
+        AssociationText,
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            "Illumination",
+            TextStyle {
+                color: Color::BLACK,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            // Above the metric plot
+            bottom: Val::Px(metrics::PLOT_HEIGHT + 10.0),
+            right: Val::Px(5.0),
+            ..default()
+        }),
+        IlluminationText,
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            "Beams",
+            TextStyle {
+                color: Color::BLACK,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            left: Val::Percent(40.0),
+            ..default()
+        }),
+        BeamText,
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                color: Color::BLACK,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            // Above the elapsed time, clear of the help text
+            bottom: Val::Px(30.0),
+            left: Val::Px(5.0),
+            ..default()
+        }),
+        InspectorText,
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            "View",
+            TextStyle {
+                color: Color::BLACK,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            left: Val::Percent(40.0),
+            ..default()
+        }),
+        Button,
+        Interaction::default(),
+        RenderModeButton,
+    ));
 }
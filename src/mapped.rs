@@ -0,0 +1,517 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::ops::{Deref, Range};
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use bevy::ecs::system::{Res, ResMut, Resource};
+use memmap2::Mmap;
+
+use crate::association::{AssociationConfig, Associator};
+use crate::binary::{check_version, optional, optional_id, MAGIC, NO_ID};
+use crate::data::{Beam, Column, Header, Histories, SimulationRun, Track};
+use crate::metrics::{MetricConfig, MetricSample, RunMetrics};
+use crate::sensor::Sensors;
+use crate::timeseries::Time;
+
+/// Number of steps read at a time when scanning a whole run
+const CHUNK: usize = 10_000;
+
+/// Where a column's arrays start in the file
+struct ColumnIndex {
+    len: usize,
+    steps: usize,
+    values: usize,
+}
+
+struct TrackIndex {
+    column: ColumnIndex,
+    /// Offset of each sample's uncertainty, and of the end of the last
+    uncertainties: Vec<usize>,
+}
+
+/// Where each array of beam values starts in the file
+struct BeamIndex {
+    /// Index of the first beam of each step, and one past the last beam of the run
+    first: Vec<usize>,
+    ids: usize,
+    widths: usize,
+    positions: usize,
+    az_widths: usize,
+    el_widths: usize,
+    min_ranges: usize,
+    max_ranges: usize,
+    dwells: usize,
+    sensors: usize,
+    steered: usize,
+    waveforms: usize,
+    waveform_names: Vec<String>,
+}
+
+/// A binary run mapped into memory, from which any range of steps can be read without
+/// loading the rest of the run. This is the one reader of the binary layout, so a run read
+/// whole is read through it as well, from its bytes.
+pub struct MappedRun<B = Mmap> {
+    bytes: B,
+    pub header: Header,
+    steps: usize,
+    elapsed: usize,
+    truths: BTreeMap<String, ColumnIndex>,
+    tracks: BTreeMap<String, TrackIndex>,
+    beams: BeamIndex,
+}
+
+impl MappedRun {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        // SAFETY: runs are written once by the converter and not modified while being viewed
+        let bytes = unsafe { Mmap::map(&file) }
+            .with_context(|| format!("failed to map {}", path.display()))?;
+        Self::index(bytes).with_context(|| format!("failed to load {}", path.display()))
+    }
+}
+
+impl<B: Deref<Target = [u8]>> MappedRun<B> {
+    /// Find the offset of every array in the run
+    pub fn index(bytes: B) -> Result<Self> {
+        ensure!(
+            bytes.starts_with(MAGIC),
+            "not a binary run, convert it first"
+        );
+        check_version(bytes.get(MAGIC.len()).copied().context("run ended early")?)?;
+        let mut offset = MAGIC.len() + 1;
+
+        let (header, length) = read_str(&bytes, offset)?;
+        let header = serde_json::from_str(header).context("invalid header")?;
+        offset += 4 + length;
+
+        let steps = read_u64(&bytes, offset)? as usize;
+        let elapsed = offset + 8;
+        offset = array_end(&bytes, elapsed, steps, 8)?;
+        let step_count = steps;
+
+        let column = |offset: &mut usize| -> Result<(String, ColumnIndex)> {
+            let (id, length) = read_str(&bytes, *offset).context("invalid id")?;
+            let id = id.to_string();
+            *offset += 4 + length;
+
+            let len = read_u64(&bytes, *offset)? as usize;
+            ensure!(len > 0, "{}: has no samples", id);
+            let steps = *offset + 8;
+            let values = array_end(&bytes, steps, len, 4)?;
+            *offset = array_end(&bytes, values, len, 6 * 4)?;
+
+            // Samples are found by binary search over their steps, so they must be in order
+            let mut previous = None;
+            for c in bytes[steps..values].chunks_exact(4) {
+                let step = u32::from_le_bytes(c.try_into().unwrap());
+                ensure!(
+                    (step as usize) < step_count,
+                    "{}: sample refers to a missing step",
+                    id
+                );
+                ensure!(
+                    previous < Some(step),
+                    "{}: samples are not in step order",
+                    id
+                );
+                previous = Some(step);
+            }
+            Ok((id, ColumnIndex { len, steps, values }))
+        };
+
+        let mut truths = BTreeMap::new();
+        let truth_count = read_u32(&bytes, offset)?;
+        offset += 4;
+        for _ in 0..truth_count {
+            let (id, index) = column(&mut offset)?;
+            truths.insert(id, index);
+        }
+
+        let mut tracks = BTreeMap::new();
+        let track_count = read_u32(&bytes, offset)?;
+        offset += 4;
+        for _ in 0..track_count {
+            let (id, column) = column(&mut offset)?;
+            let lengths = prefix_sums(&bytes, offset, column.len)?;
+            let start = array_end(&bytes, offset, column.len, 4)?;
+            let uncertainties = lengths
+                .iter()
+                .map(|total| array_end(&bytes, start, *total, 4))
+                .collect::<Result<Vec<_>>>()?;
+            offset = *uncertainties.last().unwrap();
+            tracks.insert(
+                id,
+                TrackIndex {
+                    column,
+                    uncertainties,
+                },
+            );
+        }
+
+        let first = prefix_sums(&bytes, offset, steps)?;
+        let total = *first.last().unwrap();
+        let ids = array_end(&bytes, offset, steps, 4)?;
+        let widths = array_end(&bytes, ids, total, 4)?;
+        let positions = array_end(&bytes, widths, total, 4)?;
+        let az_widths = array_end(&bytes, positions, total, 8)?;
+        let el_widths = array_end(&bytes, az_widths, total, 4)?;
+        let min_ranges = array_end(&bytes, el_widths, total, 4)?;
+        let max_ranges = array_end(&bytes, min_ranges, total, 4)?;
+        let dwells = array_end(&bytes, max_ranges, total, 4)?;
+        let sensors = array_end(&bytes, dwells, total, 4)?;
+        let steered = array_end(&bytes, sensors, total, 4)?;
+        offset = array_end(&bytes, steered, total, 1)?;
+
+        let mut waveform_names = Vec::new();
+        let waveform_count = read_u32(&bytes, offset)?;
+        offset += 4;
+        for _ in 0..waveform_count {
+            let (name, length) = read_str(&bytes, offset).context("invalid waveform")?;
+            waveform_names.push(name.to_string());
+            offset += 4 + length;
+        }
+        let waveforms = offset;
+        let end = array_end(&bytes, waveforms, total, 4)?;
+        for c in bytes[waveforms..end].chunks_exact(4) {
+            let waveform = u32::from_le_bytes(c.try_into().unwrap());
+            ensure!(
+                waveform == NO_ID || (waveform as usize) < waveform_names.len(),
+                "beam refers to a missing waveform"
+            );
+        }
+
+        let beams = BeamIndex {
+            first,
+            ids,
+            widths,
+            positions,
+            az_widths,
+            el_widths,
+            min_ranges,
+            max_ranges,
+            dwells,
+            sensors,
+            steered,
+            waveforms,
+            waveform_names,
+        };
+
+        Ok(Self {
+            bytes,
+            header,
+            steps,
+            elapsed,
+            truths,
+            tracks,
+            beams,
+        })
+    }
+
+    fn elapsed(&self, step: usize) -> f64 {
+        f64::from_le_bytes(self.array(self.elapsed + step * 8))
+    }
+
+    fn u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.array(offset))
+    }
+
+    fn f32(&self, offset: usize) -> f32 {
+        f32::from_le_bytes(self.array(offset))
+    }
+
+    fn f32s(&self, offset: usize, count: usize) -> impl Iterator<Item = f32> + '_ {
+        self.bytes[offset..offset + count * 4]
+            .chunks_exact(4)
+            .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+    }
+
+    fn array<const N: usize>(&self, offset: usize) -> [u8; N] {
+        self.bytes[offset..offset + N].try_into().unwrap()
+    }
+
+    pub fn step_count(&self) -> usize {
+        self.steps
+    }
+
+    /// Steps whose elapsed time is within the given times
+    pub fn step_range(&self, start: f64, end: f64) -> Range<usize> {
+        let first = partition_point(self.steps, |i| self.elapsed(i) < start);
+        let last = partition_point(self.steps, |i| self.elapsed(i) <= end);
+        first..last.max(first)
+    }
+
+    /// Read a range of steps into memory as a run of its own
+    pub fn read(&self, steps: Range<usize>) -> SimulationRun {
+        let mut run = SimulationRun {
+            elapsed: steps.clone().map(|i| self.elapsed(i)).collect(),
+            header: self.header.clone(),
+            ..Default::default()
+        };
+
+        // Samples of a column within the steps, with their step relative to the first step
+        let samples = |column: &ColumnIndex| {
+            let step = |i: usize| self.u32(column.steps + i * 4) as usize;
+            let first = partition_point(column.len, |i| step(i) < steps.start);
+            let last = partition_point(column.len, |i| step(i) < steps.end);
+            let relative = (first..last)
+                .map(|i| (step(i) - steps.start) as u32)
+                .collect();
+            (first..last, relative)
+        };
+        let state = |column: &ColumnIndex, i: usize| -> [f32; 6] {
+            let values: Vec<f32> = self.f32s(column.values + i * 24, 6).collect();
+            values.try_into().unwrap()
+        };
+
+        for (id, column) in self.truths.iter() {
+            let (range, steps) = samples(column);
+            if range.is_empty() {
+                continue;
+            }
+            let values = range.map(|i| state(column, i)).collect();
+            run.truths.insert(id.clone(), Column { steps, values });
+        }
+
+        for (id, track) in self.tracks.iter() {
+            let (range, steps) = samples(&track.column);
+            if range.is_empty() {
+                continue;
+            }
+            let values = range
+                .map(|i| {
+                    let [start, end] = [i, i + 1].map(|i| track.uncertainties[i]);
+                    Track {
+                        state: state(&track.column, i),
+                        uncertainty: self.f32s(start, (end - start) / 4).collect(),
+                    }
+                })
+                .collect();
+            run.tracks.insert(id.clone(), Column { steps, values });
+        }
+
+        let index = &self.beams;
+        for i in steps {
+            let beams = (index.first[i]..index.first[i + 1])
+                .map(|b| Beam {
+                    id: optional_id(self.u32(index.ids + b * 4)),
+                    width: self.f32(index.widths + b * 4),
+                    position: [
+                        self.f32(index.positions + b * 8),
+                        self.f32(index.positions + b * 8 + 4),
+                    ],
+                    az_width: optional(self.f32(index.az_widths + b * 4)),
+                    el_width: optional(self.f32(index.el_widths + b * 4)),
+                    steered: self.bytes[index.steered + b] == 1,
+                    min_range: optional(self.f32(index.min_ranges + b * 4)),
+                    max_range: optional(self.f32(index.max_ranges + b * 4)),
+                    waveform: optional_id(self.u32(index.waveforms + b * 4))
+                        .and_then(|w| index.waveform_names.get(w as usize).cloned()),
+                    dwell: optional(self.f32(index.dwells + b * 4)),
+                    sensor: self.u32(index.sensors + b * 4),
+                })
+                .collect();
+            run.beams.push(beams);
+        }
+
+        run
+    }
+
+    /// Compute the metrics of the whole run, reading a chunk of steps at a time
+    pub fn metrics(&self, config: &MetricConfig) -> RunMetrics {
+        let mut metrics = RunMetrics::default();
+        for start in (0..self.steps).step_by(CHUNK) {
+            let chunk = self.read(start..(start + CHUNK).min(self.steps));
+            metrics.samples.extend(
+                chunk
+                    .steps()
+                    .map(|step| MetricSample::new(&step, &self.header.frame, config)),
+            );
+        }
+        metrics
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    let bytes = bytes.get(offset..offset + 4).context("run ended early")?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// A string and its length in bytes, not counting the length before it
+fn read_str(bytes: &[u8], offset: usize) -> Result<(&str, usize)> {
+    let length = read_u32(bytes, offset)? as usize;
+    let s = bytes
+        .get(offset + 4..offset + 4 + length)
+        .context("run ended early")?;
+    Ok((std::str::from_utf8(s)?, length))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64> {
+    let bytes = bytes.get(offset..offset + 8).context("run ended early")?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Offset just past an array of `count` values of `size` bytes, failing if the run ends first
+fn array_end(bytes: &[u8], offset: usize, count: usize, size: usize) -> Result<usize> {
+    count
+        .checked_mul(size)
+        .and_then(|length| offset.checked_add(length))
+        .filter(|end| *end <= bytes.len())
+        .context("run ended early")
+}
+
+/// Running totals of an array of `u32`s, starting from zero and ending with the sum of them all
+fn prefix_sums(bytes: &[u8], offset: usize, count: usize) -> Result<Vec<usize>> {
+    let end = array_end(bytes, offset, count, 4)?;
+    let mut sums: Vec<usize> = vec![0];
+    for c in bytes[offset..end].chunks_exact(4) {
+        let value = u32::from_le_bytes(c.try_into().unwrap()) as usize;
+        let sum = sums.last().unwrap().checked_add(value);
+        sums.push(sum.context("run is too large")?);
+    }
+    Ok(sums)
+}
+
+/// Index of the first element for which `pred` is false, assuming it is true for a prefix
+fn partition_point(len: usize, pred: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if pred(mid) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+/// A run too large to load at once. Only the steps within a window around the current time
+/// are spawned, and the window is reloaded as playback moves out of it.
+#[derive(Resource)]
+pub struct LazyRun {
+    run: MappedRun,
+    /// Length of the loaded window, in seconds
+    span: f64,
+    window: Option<(f64, f64)>,
+    /// Steps whose samples are in the entity histories
+    loaded: Range<usize>,
+    associator: Associator,
+}
+
+impl LazyRun {
+    pub fn new(run: MappedRun, span: f64) -> Self {
+        Self {
+            run,
+            span,
+            window: None,
+            loaded: 0..0,
+            associator: Associator::default(),
+        }
+    }
+
+    pub fn metrics(&self, config: &MetricConfig) -> RunMetrics {
+        self.run.metrics(config)
+    }
+}
+
+/// Move the window around the current time once playback nears either end of it, dropping the
+/// samples that leave it and reading the steps that enter it. Association purity counts every
+/// step loaded so far, including any loaded again after the window moves back.
+pub fn update_lazy_window(
+    time: Res<Time>,
+    lazy: Option<ResMut<LazyRun>>,
+    association: Res<AssociationConfig>,
+    sensors: Res<Sensors>,
+    mut histories: Histories,
+) {
+    let Some(mut lazy) = lazy else {
+        return;
+    };
+    let margin = lazy.span / 4.0;
+    if let Some((start, end)) = lazy.window {
+        if time.0 >= start + margin && time.0 <= end - margin {
+            return;
+        }
+    }
+
+    let window = (time.0 - lazy.span / 2.0, time.0 + lazy.span / 2.0);
+    let steps = lazy.run.step_range(window.0, window.1);
+    histories.retain(window.0, window.1);
+
+    let LazyRun {
+        run,
+        loaded,
+        associator,
+        ..
+    } = &mut *lazy;
+    let before = steps.start..steps.end.min(loaded.start);
+    let after = steps.start.max(loaded.end)..steps.end;
+    let chunks: Vec<SimulationRun> = [before, after]
+        .into_iter()
+        .filter(|range| !range.is_empty())
+        .map(|range| run.read(range))
+        .collect();
+    let frame = run.header.frame;
+    let new_steps = chunks.iter().flat_map(SimulationRun::steps);
+    histories.add(new_steps, &frame, &sensors.0, associator, &association);
+
+    lazy.window = Some(window);
+    lazy.loaded = steps;
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::Cursor;
+
+    use crate::data::{SimulationRun, Step};
+
+    use super::MappedRun;
+
+    #[test]
+    fn read_windows() {
+        let lines: Vec<String> = (0..50)
+            .map(|i| {
+                let elapsed = i as f64 * 0.1;
+                let mut truths = vec![format!(r#""a": [{}, 0, 0, 0, 0, 0]"#, i)];
+                if i % 3 == 0 {
+                    truths.push(format!(r#""b": [0, {}, 0, 0, 0, 0]"#, i));
+                }
+                let uncertainty = vec!["1"; i % 4].join(", ");
+                let beams = vec![r#"{"width": 0.1, "position": [0.2, 0.3], "az_width": 0.2, "waveform": "search", "max_range": 1000}"#; i % 3].join(", ");
+                format!(
+                    r#"{{"elapsed": {}, "truths": {{{}}}, "tracks": {{"7": {{"state": [{}, 0, 0, 0, 0, 0], "uncertainty": [{}]}}}}, "beams": [{}]}}"#,
+                    elapsed,
+                    truths.join(", "),
+                    i,
+                    uncertainty,
+                    beams
+                )
+            })
+            .collect();
+        let run = SimulationRun::from_jsonl(Cursor::new(lines.join("\n")), false).unwrap();
+        let steps: Vec<Step> = run.steps().collect();
+
+        let path = std::env::temp_dir().join(format!("radar-view-{}.run", std::process::id()));
+        run.write_binary(File::create(&path).unwrap()).unwrap();
+        let mapped = MappedRun::open(&path).unwrap();
+
+        // A run cut short is rejected when it is opened, rather than when a step is read
+        let bytes = std::fs::read(&path).unwrap();
+        let short = path.with_extension("short");
+        std::fs::write(&short, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(MappedRun::open(&short).is_err());
+        std::fs::remove_file(&short).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mapped.read(0..50), run);
+        assert_eq!(mapped.step_range(1.05, 2.0), 11..21);
+        for range in [0..0, 0..7, 5..6, 13..40, 49..50] {
+            let window: Vec<Step> = mapped.read(range.clone()).steps().collect();
+            assert_eq!(window, steps[range]);
+        }
+    }
+}
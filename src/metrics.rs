@@ -0,0 +1,401 @@
+use bevy::ecs::change_detection::DetectChanges;
+use bevy::ecs::component::Component;
+use bevy::ecs::entity::Entity;
+use bevy::ecs::query::With;
+use bevy::ecs::system::{Commands, Query, Res, Resource};
+use bevy::hierarchy::{BuildChildren, DespawnRecursiveExt};
+use bevy::math::Vec3;
+use bevy::render::color::Color;
+use bevy::text::{Text, TextSection, TextStyle};
+use bevy::ui::node_bundles::{NodeBundle, TextBundle};
+use bevy::ui::{PositionType, Style, Val};
+use bevy::utils::default;
+
+use crate::data::{SimulationRun, Step};
+use crate::frame::Frame;
+use crate::mapped::LazyRun;
+use crate::timeseries::Time;
+
+/// Parameters of the OSPA and GOSPA metrics
+#[derive(clap::Args, Resource, Clone, Debug)]
+pub struct MetricConfig {
+    /// Distance at which a localization error is treated as a missed target, in meters
+    #[arg(long, default_value_t = 10_000.0)]
+    pub cutoff: f64,
+
+    /// Order of the OSPA and GOSPA metrics
+    #[arg(long, default_value_t = 2.0)]
+    pub order: f64,
+}
+
+/// The optimal assignment of rows to columns, using the Hungarian algorithm, as the column of
+/// each row. Every row is assigned, so there must be no more rows than columns.
+#[allow(clippy::needless_range_loop)]
+pub fn assignment(cost: &[Vec<f64>]) -> Vec<usize> {
+    let n = cost.len();
+    let m = cost.first().map(|row| row.len()).unwrap_or_default();
+    assert!(n <= m);
+
+    // Potentials and matching are 1-indexed, with 0 as a sentinel column
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; m + 1];
+    let mut matched = vec![0; m + 1];
+    let mut way = vec![0; m + 1];
+    for i in 1..=n {
+        matched[0] = i;
+        let mut j0 = 0;
+        let mut min = vec![f64::INFINITY; m + 1];
+        let mut used = vec![false; m + 1];
+        loop {
+            used[j0] = true;
+            let i0 = matched[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=m {
+                if used[j] {
+                    continue;
+                }
+                let reduced = cost[i0 - 1][j - 1] - u[i0] - v[j];
+                if reduced < min[j] {
+                    min[j] = reduced;
+                    way[j] = j0;
+                }
+                if min[j] < delta {
+                    delta = min[j];
+                    j1 = j;
+                }
+            }
+            for j in 0..=m {
+                if used[j] {
+                    u[matched[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if matched[j0] == 0 {
+                break;
+            }
+        }
+        while j0 != 0 {
+            let j1 = way[j0];
+            matched[j0] = matched[j1];
+            j0 = j1;
+        }
+    }
+
+    let mut columns = vec![0; n];
+    for j in 1..=m {
+        if matched[j] != 0 {
+            columns[matched[j] - 1] = j - 1;
+        }
+    }
+    columns
+}
+
+/// Cost of the optimal assignment of rows to columns
+fn assignment_cost(cost: &[Vec<f64>]) -> f64 {
+    assignment(cost)
+        .into_iter()
+        .enumerate()
+        .map(|(i, j)| cost[i][j])
+        .sum()
+}
+
+/// Sum of the cutoff distances of the optimal assignment between `a` and `b`
+fn localization_cost(a: &[Vec3], b: &[Vec3], config: &MetricConfig) -> f64 {
+    let (rows, cols) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    let cost: Vec<Vec<f64>> = rows
+        .iter()
+        .map(|x| {
+            cols.iter()
+                .map(|y| {
+                    (x.distance(*y) as f64)
+                        .min(config.cutoff)
+                        .powf(config.order)
+                })
+                .collect()
+        })
+        .collect();
+    assignment_cost(&cost)
+}
+
+/// Optimal subpattern assignment distance between two sets of positions
+pub fn ospa(truths: &[Vec3], tracks: &[Vec3], config: &MetricConfig) -> f64 {
+    let n = truths.len().max(tracks.len());
+    if n == 0 {
+        return 0.0;
+    }
+    let cardinality = truths.len().abs_diff(tracks.len()) as f64;
+    let cost =
+        localization_cost(truths, tracks, config) + config.cutoff.powf(config.order) * cardinality;
+    (cost / n as f64).powf(1.0 / config.order)
+}
+
+/// Generalized optimal subpattern assignment distance between two sets of positions, with
+/// `alpha = 2` so that it decomposes into localization, missed and false target errors.
+pub fn gospa(truths: &[Vec3], tracks: &[Vec3], config: &MetricConfig) -> f64 {
+    let cardinality = truths.len().abs_diff(tracks.len()) as f64;
+    let cost = localization_cost(truths, tracks, config)
+        + config.cutoff.powf(config.order) / 2.0 * cardinality;
+    cost.powf(1.0 / config.order)
+}
+
+/// OSPA and GOSPA at a single step of a run
+#[derive(Clone, Debug)]
+pub struct MetricSample {
+    pub elapsed: f64,
+    pub ospa: f64,
+    pub gospa: f64,
+}
+
+impl MetricSample {
+    pub fn new(step: &Step, frame: &Frame, config: &MetricConfig) -> Self {
+        let truths: Vec<Vec3> = step
+            .truths
+            .values()
+            .map(|truth| frame.state(truth).pos)
+            .collect();
+        let tracks: Vec<Vec3> = step
+            .tracks
+            .values()
+            .map(|track| track.state(frame).pos)
+            .collect();
+        Self {
+            elapsed: step.elapsed,
+            ospa: ospa(&truths, &tracks, config),
+            gospa: gospa(&truths, &tracks, config),
+        }
+    }
+}
+
+/// Metrics computed at every step of the current run
+#[derive(Resource, Default)]
+pub struct RunMetrics {
+    pub samples: Vec<MetricSample>,
+}
+
+impl RunMetrics {
+    pub fn new(sim: &SimulationRun, config: &MetricConfig) -> Self {
+        Self {
+            samples: sim
+                .steps()
+                .map(|step| MetricSample::new(&step, &sim.header.frame, config))
+                .collect(),
+        }
+    }
+
+    /// Get the last sample at or before the given time
+    pub fn at_or_before(&self, time: f64) -> Option<&MetricSample> {
+        let index = self.samples.partition_point(|s| s.elapsed <= time);
+        index.checked_sub(1).map(|i| &self.samples[i])
+    }
+
+    /// Print the metrics as whitespace separated columns
+    pub fn print(&self) {
+        println!("elapsed\tospa\tgospa");
+        for sample in self.samples.iter() {
+            println!("{}\t{}\t{}", sample.elapsed, sample.ospa, sample.gospa);
+        }
+    }
+}
+
+/// Recompute the metrics whenever a new run is loaded. Lazily loaded runs compute theirs upfront.
+pub fn compute_metrics(
+    mut commands: Commands,
+    sim: Res<SimulationRun>,
+    lazy: Option<Res<LazyRun>>,
+    config: Res<MetricConfig>,
+) {
+    if sim.is_changed() && lazy.is_none() {
+        commands.insert_resource(RunMetrics::new(&sim, &config));
+    }
+}
+
+const OSPA_COLOR: Color = Color::BLUE;
+const GOSPA_COLOR: Color = Color::RED;
+
+/// Height of the metric plot in the bottom right corner, so other text there can sit above it
+pub const PLOT_HEIGHT: f32 = 120.0;
+
+#[derive(Component)]
+pub struct MetricPlot;
+
+#[derive(Component)]
+pub struct MetricCursor;
+
+#[derive(Component)]
+pub struct MetricText;
+
+/// Rebuild the metric plot whenever the metrics are recomputed
+pub fn update_metric_plot(
+    mut commands: Commands,
+    metrics: Option<Res<RunMetrics>>,
+    plots: Query<Entity, With<MetricPlot>>,
+) {
+    const POINTS: usize = 300;
+
+    let Some(metrics) = metrics else {
+        return;
+    };
+    if !metrics.is_changed() {
+        return;
+    }
+    for plot in plots.iter() {
+        commands.entity(plot).despawn_recursive();
+    }
+
+    let (first, last) = match (metrics.samples.first(), metrics.samples.last()) {
+        (Some(first), Some(last)) => (first.elapsed, last.elapsed),
+        _ => return,
+    };
+    let duration = (last - first).max(f64::EPSILON);
+    let max = metrics
+        .samples
+        .iter()
+        .map(|s| s.ospa.max(s.gospa))
+        .fold(f64::EPSILON, f64::max);
+    let stride = metrics.samples.len().div_ceil(POINTS);
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(5.0),
+                    right: Val::Px(5.0),
+                    width: Val::Px(400.0),
+                    height: Val::Px(PLOT_HEIGHT),
+                    ..default()
+                },
+                background_color: Color::rgba(0.9, 0.9, 0.9, 0.8).into(),
+                ..default()
+            },
+            MetricPlot,
+        ))
+        .with_children(|plot| {
+            for sample in metrics.samples.iter().step_by(stride) {
+                let left = 100.0 * (sample.elapsed - first) / duration;
+                for (value, color) in [(sample.ospa, OSPA_COLOR), (sample.gospa, GOSPA_COLOR)] {
+                    plot.spawn(NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            left: Val::Percent(left as f32),
+                            bottom: Val::Percent((100.0 * value / max) as f32),
+                            width: Val::Px(2.0),
+                            height: Val::Px(2.0),
+                            ..default()
+                        },
+                        background_color: color.into(),
+                        ..default()
+                    });
+                }
+            }
+
+            plot.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Percent(0.0),
+                        width: Val::Px(1.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    background_color: Color::BLACK.into(),
+                    ..default()
+                },
+                MetricCursor,
+            ));
+
+            plot.spawn((
+                TextBundle::from_sections([
+                    TextSection::new("OSPA", style(OSPA_COLOR)),
+                    TextSection::new("GOSPA", style(GOSPA_COLOR)),
+                ])
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(2.0),
+                    left: Val::Px(2.0),
+                    ..default()
+                }),
+                MetricText,
+            ));
+        });
+}
+
+fn style(color: Color) -> TextStyle {
+    TextStyle {
+        font_size: 14.0,
+        color,
+        ..default()
+    }
+}
+
+/// Move the plot cursor and current values to the current time
+pub fn update_metric_cursor(
+    time: Res<Time>,
+    metrics: Option<Res<RunMetrics>>,
+    mut cursors: Query<&mut Style, With<MetricCursor>>,
+    mut texts: Query<&mut Text, With<MetricText>>,
+) {
+    let Some(metrics) = metrics else {
+        return;
+    };
+    let (first, last) = match (metrics.samples.first(), metrics.samples.last()) {
+        (Some(first), Some(last)) => (first.elapsed, last.elapsed),
+        _ => return,
+    };
+
+    let position = ((time.0 - first) / (last - first).max(f64::EPSILON)).clamp(0.0, 1.0);
+    for mut cursor in &mut cursors {
+        cursor.left = Val::Percent(100.0 * position as f32);
+    }
+
+    let sample = metrics.at_or_before(time.0);
+    for mut text in &mut texts {
+        text.sections[0].value = match sample {
+            Some(s) => format!("OSPA {:.1}m  ", s.ospa),
+            None => "OSPA -  ".into(),
+        };
+        text.sections[1].value = match sample {
+            Some(s) => format!("GOSPA {:.1}m", s.gospa),
+            None => "GOSPA -".into(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::math::Vec3;
+
+    use super::{assignment_cost, gospa, ospa, MetricConfig};
+
+    #[test]
+    fn hungarian() {
+        let cost = vec![
+            vec![4.0, 1.0, 3.0],
+            vec![2.0, 0.0, 5.0],
+            vec![3.0, 2.0, 2.0],
+        ];
+        assert_eq!(assignment_cost(&cost), 5.0);
+
+        let cost = vec![vec![1.0, 2.0, 0.5], vec![1.0, 9.0, 9.0]];
+        assert_eq!(assignment_cost(&cost), 1.5);
+    }
+
+    #[test]
+    fn ospa_gospa() {
+        let config = MetricConfig {
+            cutoff: 10.0,
+            order: 1.0,
+        };
+        let truths = [Vec3::ZERO];
+        let tracks = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(100.0, 0.0, 0.0)];
+
+        assert!((ospa(&truths, &tracks, &config) - 5.5).abs() < 1e-6);
+        assert!((gospa(&truths, &tracks, &config) - 6.0).abs() < 1e-6);
+        assert_eq!(ospa(&[], &[], &config), 0.0);
+        assert!((ospa(&truths, &[], &config) - 10.0).abs() < 1e-6);
+    }
+}
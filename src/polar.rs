@@ -1,6 +1,6 @@
-use bevy::math::Vec3;
+use bevy::math::{Quat, Vec3};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct PolarVec3 {
     pub range: f32,
     pub azimuth: f32,
@@ -23,6 +23,11 @@ impl PolarVec3 {
     }
 }
 
+/// Rotation from the boresight to the given azimuth and elevation
+pub fn pointing(azimuth: f32, elevation: f32) -> Quat {
+    Quat::from_rotation_y(azimuth) * Quat::from_rotation_x(-elevation)
+}
+
 impl From<Vec3> for PolarVec3 {
     fn from(val: Vec3) -> Self {
         let range = val.length();
@@ -46,7 +51,7 @@ impl From<PolarVec3> for Vec3 {
 
 #[cfg(test)]
 mod test {
-    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+    use std::f32::consts::FRAC_PI_4;
 
     use bevy::math::Vec3;
 
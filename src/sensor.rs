@@ -0,0 +1,109 @@
+use bevy::ecs::system::Resource;
+use bevy::math::{Quat, Vec3};
+use serde::{Deserialize, Serialize};
+
+use crate::fov::FoV;
+use crate::frame::Frame;
+use crate::polar::{pointing, PolarVec3};
+
+/// A sensor as declared in the run header
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SensorConfig {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Position in the input frame, in meters
+    #[serde(default)]
+    pub position: [f32; 3],
+    /// Azimuth and elevation of the sensor's boresight in radians. These are scene angles, so
+    /// the frame does not apply: azimuth is from the direction every frame looks along towards
+    /// the left, and elevation is upwards.
+    #[serde(default)]
+    pub boresight: [f32; 2],
+    #[serde(default)]
+    pub fov: FoV,
+}
+
+/// A sensor placed in the scene. Beam positions and FoVs are relative to its boresight.
+#[derive(Debug, Clone)]
+pub struct Sensor {
+    pub name: String,
+    pub origin: Vec3,
+    /// Rotation from the scene's boresight to the sensor's
+    pub rotation: Quat,
+    pub fov: FoV,
+}
+
+impl Sensor {
+    pub fn new(index: usize, config: &SensorConfig, frame: &Frame) -> Self {
+        let [az, el] = config.boresight;
+        Self {
+            name: config.name.clone().unwrap_or_else(|| index.to_string()),
+            origin: frame.rotation() * Vec3::from_array(config.position),
+            rotation: pointing(az, el),
+            fov: config.fov,
+        }
+    }
+
+    /// Convert a position relative to the sensor's boresight into the scene
+    pub fn to_scene(&self, p: PolarVec3) -> Vec3 {
+        self.origin + self.rotation * Vec3::from(p)
+    }
+
+    /// Convert a position in the scene into one relative to the sensor's boresight
+    pub fn to_sensor(&self, p: Vec3) -> PolarVec3 {
+        PolarVec3::from(self.rotation.inverse() * (p - self.origin))
+    }
+}
+
+impl Default for Sensor {
+    fn default() -> Self {
+        Self::new(0, &SensorConfig::default(), &Frame::default())
+    }
+}
+
+/// Every sensor of the current run. There is always at least one, and the first is the one the
+/// coverage heatmap is drawn for.
+#[derive(Resource, Debug)]
+pub struct Sensors(pub Vec<Sensor>);
+
+impl Default for Sensors {
+    fn default() -> Self {
+        Self(vec![Sensor::default()])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::math::Vec3;
+
+    use crate::beam::BeamKey;
+    use crate::data::{Beam, Line};
+    use crate::polar::PolarVec3;
+
+    #[test]
+    fn sensors_from_header() {
+        let line = r#"{"sensors": [{}, {"name": "east", "position": [0, 1000, 0], "boresight": [1.5707964, 0], "fov": {"range": 5000, "az": [-0.5, 0.5], "el": [0, 0.8]}}]}"#;
+        let Ok(Line::Header(header)) = Line::parse(line) else {
+            panic!("expected a header");
+        };
+        let sensors = header.sensors();
+        assert_eq!(sensors[0].name, "0");
+        assert_eq!(sensors[1].name, "east");
+        assert_eq!(sensors[1].fov.el, [0.0, 0.8]);
+
+        // The second sensor is to the right of the first, looking right
+        let ahead = sensors[1].to_scene(PolarVec3::new(100.0, 0.0, 0.0));
+        assert!(ahead.distance(Vec3::new(1100.0, 0.0, 0.0)) < 0.01);
+        let back = sensors[1].to_sensor(ahead);
+        assert!((back.range - 100.0).abs() < 0.01);
+        assert!(back.azimuth.abs() < 1e-4 && back.elevation.abs() < 1e-4);
+
+        let beam: Beam =
+            serde_json::from_str(r#"{"width": 0.1, "position": [0, 0], "sensor": 1}"#).unwrap();
+        let state = beam.state(BeamKey::Position(0), &sensors);
+        assert_eq!(state.target.range, 5000.0);
+        assert!(state.illuminates(Vec3::new(3000.0, 0.0, 0.0)));
+        assert!(!state.illuminates(Vec3::new(0.0, 0.0, 3000.0)));
+    }
+}
@@ -1,9 +1,9 @@
-use crate::timeseries::Time;
-use crate::{polar::PolarVec3, state, timeseries, RenderMode};
+use crate::timeseries::{Interpolate, Time};
+use crate::truth::Truth;
+use crate::{camera::Views, state, timeseries};
 use bevy::ecs::system::Res;
 use bevy::{
-    ecs::{component::Component, system::Query},
-    gizmos::gizmos::Gizmos,
+    ecs::{component::Component, query::With, system::Query},
     math::{Quat, Vec3},
     render::color::Color,
 };
@@ -14,62 +14,54 @@ pub struct State {
     pub vel: Vec3,
 }
 
-impl State {
-    pub fn with_xyz(mut self, x: f32, y: f32, z: f32) -> Self {
-        self.pos = Vec3::new(x, y, z);
-        self
+impl Interpolate for State {
+    fn interpolate(&self, next: &Self, s: f32) -> Self {
+        Self {
+            pos: self.pos.lerp(next.pos, s),
+            vel: self.vel.lerp(next.vel, s),
+        }
     }
 
-    pub fn with_vel(mut self, x: f32, y: f32, z: f32) -> Self {
-        self.vel = Vec3::new(x, y, z);
-        self
+    /// Extrapolate assuming a constant velocity
+    fn extrapolate(&self, dt: f32) -> Self {
+        Self {
+            pos: self.pos + self.vel * dt,
+            vel: self.vel,
+        }
     }
 }
 
 pub fn render_states(
-    mode: Res<RenderMode>,
-    truth_query: Query<(&state::State, &timeseries::Active)>,
-    mut gizmos: Gizmos,
+    mut views: Views,
+    truth_query: Query<(&state::State, &timeseries::Active), With<Truth>>,
 ) {
     let color = Color::BLACK;
-    for (state, active) in truth_query.iter() {
-        if !active.0 {
-            continue;
-        }
-        match mode.as_ref() {
-            RenderMode::Cartesian => {
-                gizmos.sphere(state.pos, Quat::default(), 1000.0, color);
-            }
-            RenderMode::Spherical => {
-                let polar: PolarVec3 = state.pos.into();
-                gizmos.sphere(polar.direct_vec3(), Quat::default(), 0.006, color);
+    for (view, gizmos) in views.iter_mut() {
+        for (state, active) in truth_query.iter() {
+            if !active.0 {
+                continue;
             }
+            let size = view.marker_size();
+            gizmos.sphere(view.project(state.pos), Quat::default(), size, color);
         }
     }
 }
 
 pub fn render_history(
     time: Res<Time>,
-    mode: Res<RenderMode>,
-    truth_query: Query<(&timeseries::TimeSeries<State>, &timeseries::Active)>,
-    mut gizmos: Gizmos,
+    mut views: Views,
+    truth_query: Query<(&timeseries::TimeSeries<State>, &timeseries::Active), With<Truth>>,
 ) {
     let color = Color::BLACK;
-    for (series, active) in truth_query.iter() {
-        if !active.0 {
-            continue;
-        }
-        match mode.as_ref() {
-            RenderMode::Cartesian => {
-                gizmos.linestrip(series.before(time.0).map(|state| state.pos), color)
-                //gizmos.sphere(state.pos, Quat::default(), 1000.0, color);
+    for (view, gizmos) in views.iter_mut() {
+        for (series, active) in truth_query.iter() {
+            if !active.0 {
+                continue;
             }
-            RenderMode::Spherical => gizmos.linestrip(
-                series
-                    .before(time.0)
-                    .map(|state| PolarVec3::from(state.pos).direct_vec3()),
+            gizmos.linestrip(
+                series.before(time.0).map(|state| view.project(state.pos)),
                 color,
-            ),
+            );
         }
     }
 }
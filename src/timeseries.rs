@@ -1,52 +1,155 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use bevy::ecs::component::Component;
 use bevy::ecs::query::With;
 use bevy::ecs::system::{Query, Res, ResMut, Resource};
+use bevy::log::warn;
 use bevy::text::Text;
 
+/// Samples of a value over time, kept sorted by time
 #[derive(Component)]
 pub struct TimeSeries<T>
 where
     T: Clone + Component,
 {
     history: Vec<(f64, T)>,
+    /// Index of the most recent lookup, so playback rarely needs to search
+    cursor: AtomicUsize,
 }
 
 impl<T> TimeSeries<T>
 where
     T: Clone + Component,
 {
-    pub fn new(history: Vec<(f64, T)>) -> Self {
-        Self { history }
+    pub fn new(mut history: Vec<(f64, T)>) -> Self {
+        if !history.windows(2).all(|w| w[0].0 <= w[1].0) {
+            warn!("time series samples are out of order, sorting them");
+            history.sort_by(|a, b| a.0.total_cmp(&b.0));
+        }
+        Self {
+            history,
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add a value, keeping the history sorted. Appending in time order is cheapest.
+    pub fn push(&mut self, time: f64, value: T) {
+        match self.history.last() {
+            Some((last, _)) if *last > time => {
+                let index = self.history.partition_point(|(t, _)| *t <= time);
+                self.history.insert(index, (time, value));
+            }
+            _ => self.history.push((time, value)),
+        }
     }
 
     pub fn before(&self, time: f64) -> impl Iterator<Item = &T> {
-        self.history
-            .iter()
-            .filter_map(move |(t, v)| if *t <= time { Some(v) } else { None })
-    }
-
-    /// Get the value closest to the given time without going past it
-    pub fn at_or_before(&self, time: f64) -> Option<&T> {
-        self.history
-            .iter()
-            .filter_map(move |(t, v)| {
-                if *t <= time && (*t - time) > -0.01 {
-                    Some(v)
-                } else {
-                    None
-                }
-            })
-            .last()
+        self.range(f64::NEG_INFINITY, time).iter().map(|(_, v)| v)
+    }
+
+    /// Drop the samples outside the two times, inclusive
+    pub fn retain(&mut self, start: f64, end: f64) {
+        self.history.retain(|(t, _)| *t >= start && *t <= end);
+        self.cursor.store(0, Ordering::Relaxed);
+    }
+
+    /// Times of the first and last samples
+    pub fn span(&self) -> Option<(f64, f64)> {
+        Some((self.history.first()?.0, self.history.last()?.0))
+    }
+
+    /// All samples between the two times, inclusive
+    pub fn range(&self, start: f64, end: f64) -> &[(f64, T)] {
+        let first = self.history.partition_point(|(t, _)| *t < start);
+        let last = self.history.partition_point(|(t, _)| *t <= end);
+        &self.history[first..last.max(first)]
+    }
+
+    /// Index of the sample closest to the given time without going past it. Checks the
+    /// previous lookup and the sample after it before falling back to a binary search.
+    fn index_at_or_before(&self, time: f64) -> Option<usize> {
+        let is_at_or_before = |i: usize| {
+            i < self.history.len()
+                && self.history[i].0 <= time
+                && self.history.get(i + 1).is_none_or(|(t, _)| *t > time)
+        };
+
+        let cursor = self.cursor.load(Ordering::Relaxed);
+        let index = if is_at_or_before(cursor) {
+            cursor
+        } else if is_at_or_before(cursor + 1) {
+            cursor + 1
+        } else {
+            self.history
+                .partition_point(|(t, _)| *t <= time)
+                .checked_sub(1)?
+        };
+        self.cursor.store(index, Ordering::Relaxed);
+        Some(index)
+    }
+
+    /// Get the sample closest to the given time without going past it
+    pub fn at_or_before(&self, time: f64) -> Option<&(f64, T)> {
+        self.index_at_or_before(time).map(|i| &self.history[i])
+    }
+
+    /// Get the first sample after the given time
+    pub fn after(&self, time: f64) -> Option<&(f64, T)> {
+        match self.index_at_or_before(time) {
+            Some(i) => self.history.get(i + 1),
+            None => self.history.first(),
+        }
+    }
+}
+
+impl<T> TimeSeries<T>
+where
+    T: Clone + Component + Interpolate,
+{
+    /// Get the value at the given time, interpolated between the samples either side of it.
+    /// Samples are only valid for `staleness` seconds, after which there is no value.
+    pub fn at(&self, time: f64, staleness: f64) -> Option<T> {
+        let (t0, before) = self.at_or_before(time)?;
+        match self.after(time) {
+            Some((t1, after)) if t1 - t0 <= staleness => {
+                Some(before.interpolate(after, ((time - t0) / (t1 - t0)) as f32))
+            }
+            _ if time - t0 <= staleness => Some(before.extrapolate((time - t0) as f32)),
+            _ => None,
+        }
+    }
+}
+
+/// Values that can be estimated between the samples of a `TimeSeries`
+pub trait Interpolate: Clone {
+    /// Estimate the value a fraction `s` of the way from this sample to the next
+    fn interpolate(&self, _next: &Self, _s: f32) -> Self {
+        self.clone()
+    }
+
+    /// Estimate the value `dt` seconds after this sample, when there is no next sample
+    fn extrapolate(&self, _dt: f32) -> Self {
+        self.clone()
     }
 }
 
+/// How long a sample remains valid without a following sample, in seconds
+#[derive(Resource, Debug)]
+pub struct Staleness(pub f64);
+
 #[derive(Resource)]
 pub struct Time(pub f64);
 
+/// Time playback starts at, and returns to on restart
+#[derive(Resource, Debug)]
+pub struct StartTime(pub f64);
+
 #[derive(Resource, Debug)]
 pub struct TimeFlow {
     pub delta: f64,
     pub paused: bool,
+    /// Hold the time at the latest step received from a live feed
+    pub pin_latest: bool,
 }
 
 impl Default for TimeFlow {
@@ -54,6 +157,7 @@ impl Default for TimeFlow {
         Self {
             delta: 0.01,
             paused: false,
+            pin_latest: false,
         }
     }
 }
@@ -64,16 +168,17 @@ pub struct Active(pub bool);
 // Updates the state of all elements to be the latest value prior to the given time
 pub fn update_current_time<T>(
     time: Res<Time>,
+    staleness: Res<Staleness>,
     mut query: Query<(&TimeSeries<T>, &mut T, &mut Active)>,
 ) where
-    T: Clone + Component,
+    T: Clone + Component + Interpolate,
 {
     for (time_series, mut current, mut active) in query.iter_mut() {
-        match time_series.at_or_before(time.0) {
+        match time_series.at(time.0, staleness.0) {
             None => *active = Active(false),
             Some(value) => {
                 *active = Active(true);
-                *current = value.clone();
+                *current = value;
             }
         }
     }
@@ -95,3 +200,78 @@ pub fn elapsed_text_update(time: Res<Time>, mut query: Query<&mut Text, With<Ela
         text.sections[0].value = format!("Elapsed: {:.3}s", time.0);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bevy::ecs::component::Component;
+
+    use super::{Interpolate, TimeSeries};
+
+    #[derive(Clone, Debug, PartialEq, Component)]
+    struct Value(f32);
+
+    impl Interpolate for Value {
+        fn interpolate(&self, next: &Self, s: f32) -> Self {
+            Self(self.0 + (next.0 - self.0) * s)
+        }
+
+        fn extrapolate(&self, dt: f32) -> Self {
+            Self(self.0 + dt)
+        }
+    }
+
+    #[test]
+    fn interpolation() {
+        let series = TimeSeries::new(vec![
+            (1.0, Value(0.0)),
+            (2.0, Value(10.0)),
+            (5.0, Value(0.0)),
+        ]);
+
+        assert_eq!(series.at(0.5, 1.0), None);
+        assert_eq!(series.at(1.0, 1.0), Some(Value(0.0)));
+        assert_eq!(series.at(1.25, 1.0), Some(Value(2.5)));
+
+        // The gap to the last sample is longer than the staleness
+        assert_eq!(series.at(2.5, 1.0), Some(Value(10.5)));
+        assert_eq!(series.at(3.5, 1.0), None);
+        assert_eq!(series.at(3.5, 3.0), Some(Value(5.0)));
+
+        assert_eq!(series.at(5.5, 1.0), Some(Value(0.5)));
+        assert_eq!(series.at(6.5, 1.0), None);
+
+        // Jumping back before the cached cursor
+        assert_eq!(series.at(1.25, 1.0), Some(Value(2.5)));
+    }
+
+    #[test]
+    fn sorted_lookup() {
+        let mut series = TimeSeries::new(vec![(2.0, Value(2.0)), (1.0, Value(1.0))]);
+        series.push(4.0, Value(4.0));
+        series.push(3.0, Value(3.0));
+
+        let values: Vec<_> = series.before(3.5).map(|v| v.0).collect();
+        assert_eq!(values, vec![1.0, 2.0, 3.0]);
+
+        let range: Vec<_> = series.range(2.0, 3.0).iter().map(|(t, _)| *t).collect();
+        assert_eq!(range, vec![2.0, 3.0]);
+        assert!(series.range(3.0, 2.0).is_empty());
+
+        for (time, expected) in [
+            (0.5, None),
+            (1.0, Some(1.0)),
+            (2.5, Some(2.0)),
+            (9.0, Some(4.0)),
+        ] {
+            assert_eq!(series.at_or_before(time).map(|(t, _)| *t), expected);
+        }
+        assert_eq!(series.after(0.5).map(|(t, _)| *t), Some(1.0));
+        assert_eq!(series.after(4.0).map(|(t, _)| *t), None);
+
+        series.retain(2.0, 3.5);
+        assert_eq!(series.span(), Some((2.0, 3.0)));
+        assert_eq!(series.at_or_before(9.0).map(|(t, _)| *t), Some(3.0));
+        series.retain(1.0, 1.5);
+        assert_eq!(series.span(), None);
+    }
+}
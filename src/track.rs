@@ -0,0 +1,137 @@
+use std::f32::consts::TAU;
+
+use crate::association::Association;
+use crate::covariance::Covariance;
+use crate::timeseries::{Active, Time, TimeSeries};
+use crate::{camera::Views, state::State, timeseries, RenderMode};
+use bevy::ecs::system::Res;
+use bevy::{
+    ecs::{bundle::Bundle, component::Component, query::With, system::Query},
+    math::Quat,
+    render::color::Color,
+    transform::components::Transform,
+};
+
+/// Color used to draw tracks, distinct from the black truths
+const TRACK_COLOR: Color = Color::FUCHSIA;
+
+/// Sigma levels drawn around each track, and the opacity of each
+const SIGMA_LEVELS: [(f32, f32); 3] = [(1.0, 0.8), (2.0, 0.5), (3.0, 0.25)];
+
+/// Marks an entity as a tracker output, with the tracker's id for it
+#[derive(Component, Debug)]
+pub struct Track(pub String);
+
+#[derive(Bundle)]
+pub struct TrackBundle {
+    pub state: State,
+    pub history: TimeSeries<State>,
+    pub covariance: Covariance,
+    pub covariance_history: TimeSeries<Covariance>,
+    pub association: Association,
+    pub association_history: TimeSeries<Association>,
+    pub active: Active,
+    pub track: Track,
+}
+
+impl TrackBundle {
+    /// A track starting at its first sample, if it has any
+    pub fn new(
+        id: String,
+        history: Vec<(f64, State)>,
+        covariances: Vec<(f64, Covariance)>,
+        associations: Vec<(f64, Association)>,
+    ) -> Option<Self> {
+        Some(Self {
+            state: history.first()?.1.clone(),
+            history: TimeSeries::new(history),
+            covariance: covariances.first().map(|c| c.1.clone()).unwrap_or_default(),
+            covariance_history: TimeSeries::new(covariances),
+            association: associations
+                .first()
+                .map(|a| a.1.clone())
+                .unwrap_or_default(),
+            association_history: TimeSeries::new(associations),
+            active: Active(false),
+            track: Track(id),
+        })
+    }
+}
+
+pub fn render_tracks(
+    mut views: Views,
+    track_query: Query<(&State, &timeseries::Active), With<Track>>,
+) {
+    for (view, gizmos) in views.iter_mut() {
+        for (state, active) in track_query.iter() {
+            if !active.0 {
+                continue;
+            }
+            let size = view.marker_size();
+            gizmos.sphere(view.project(state.pos), Quat::default(), size, TRACK_COLOR);
+        }
+    }
+}
+
+pub fn render_track_history(
+    time: Res<Time>,
+    mut views: Views,
+    track_query: Query<(&timeseries::TimeSeries<State>, &timeseries::Active), With<Track>>,
+) {
+    for (view, gizmos) in views.iter_mut() {
+        for (series, active) in track_query.iter() {
+            if !active.0 {
+                continue;
+            }
+            gizmos.linestrip(
+                series.before(time.0).map(|state| view.project(state.pos)),
+                TRACK_COLOR,
+            );
+        }
+    }
+}
+
+pub fn render_covariance(
+    mut views: Views,
+    track_query: Query<(&State, &Covariance, &timeseries::Active), With<Track>>,
+) {
+    const SEGMENTS: usize = 32;
+
+    for (view, gizmos) in views.iter_mut() {
+        for (state, covariance, active) in track_query.iter() {
+            if !active.0 {
+                continue;
+            }
+            match view.mode {
+                RenderMode::Spherical => {
+                    // Spherical errors are drawn as a box of the range, azimuth and elevation errors
+                    let center = view.project(state.pos);
+                    let size = covariance.polar_sigma(state.pos).direct_vec3();
+                    for (sigma, alpha) in SIGMA_LEVELS {
+                        let transform =
+                            Transform::from_translation(center).with_scale(2.0 * sigma * size);
+                        gizmos.cuboid(transform, TRACK_COLOR.with_a(alpha));
+                    }
+                }
+                _ => {
+                    // Ellipsoids are drawn as the three ellipses in the planes of its principal
+                    // axes, then projected into the view
+                    let axes = covariance
+                        .principal_axes()
+                        .map(|(variance, axis)| variance.sqrt() * axis);
+                    for (sigma, alpha) in SIGMA_LEVELS {
+                        let color = TRACK_COLOR.with_a(alpha);
+                        for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+                            let positions = (0..=SEGMENTS).map(|i| {
+                                let theta = TAU * (i as f32) / (SEGMENTS as f32);
+                                let offset = theta.cos() * axes[a] + theta.sin() * axes[b];
+                                view.project(state.pos + sigma * offset)
+                            });
+                            gizmos.linestrip(positions, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use bevy::ecs::{bundle::Bundle, component::Component};
+
+use crate::illumination::Illumination;
+use crate::state::State;
+use crate::timeseries::{Active, TimeSeries};
+
+/// Marks an entity as a ground truth target, with the simulation's id for it
+#[derive(Component, Debug)]
+pub struct Truth(pub String);
+
+#[derive(Bundle)]
+pub struct TruthBundle {
+    pub state: State,
+    pub history: TimeSeries<State>,
+    pub active: Active,
+    pub truth: Truth,
+    pub illumination: Illumination,
+}
+
+impl TruthBundle {
+    /// A truth starting at its first sample, if it has any
+    pub fn new(id: String, history: Vec<(f64, State)>) -> Option<Self> {
+        Some(Self {
+            state: history.first()?.1.clone(),
+            history: TimeSeries::new(history),
+            active: Active(false),
+            truth: Truth(id),
+            illumination: Illumination::default(),
+        })
+    }
+}
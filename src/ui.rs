@@ -1,27 +1,34 @@
 use bevy::{
     ecs::{
+        change_detection::DetectChanges,
         component::Component,
-        query::With,
+        query::{Changed, With},
         system::{Query, Res, ResMut},
     },
     input::keyboard::KeyCode,
     input::Input,
     text::Text,
+    ui::Interaction,
 };
 
-use crate::timeseries::{Time, TimeFlow};
+use crate::camera::{RhiCut, SceneCamera, SplitScreen};
+use crate::coverage::Coverage;
+use crate::grid::GridConfig;
+use crate::timeseries::{StartTime, Time, TimeFlow};
+use crate::RenderMode;
 
 #[derive(Component)]
 pub struct TimeControlText;
 
 pub fn time_control(
     keycode: Res<Input<KeyCode>>,
+    start: Res<StartTime>,
     mut time: ResMut<Time>,
     mut flow: ResMut<TimeFlow>,
     mut query: Query<&mut Text, With<TimeControlText>>,
 ) {
     if keycode.just_pressed(KeyCode::R) {
-        time.0 = 0.0;
+        time.0 = start.0;
     }
 
     if keycode.just_pressed(KeyCode::Comma) {
@@ -53,6 +60,72 @@ pub fn time_control(
     if keycode.just_pressed(KeyCode::Space) {
         flow.paused = !flow.paused;
     }
+
+    if keycode.just_pressed(KeyCode::L) {
+        flow.pin_latest = !flow.pin_latest;
+    }
     let mut text = query.single_mut();
     text.sections[1].value = format!("{:?}", flow);
 }
+
+/// Cycle the beam coverage heatmap between off, dwell counts and revisit times
+pub fn coverage_control(keycode: Res<Input<KeyCode>>, mut coverage: ResMut<Coverage>) {
+    if keycode.just_pressed(KeyCode::H) {
+        coverage.mode = coverage.mode.next();
+    }
+}
+
+/// Show or hide the grid and its labels
+pub fn grid_control(keycode: Res<Input<KeyCode>>, mut grid: ResMut<GridConfig>) {
+    if keycode.just_pressed(KeyCode::G) {
+        grid.show = !grid.show;
+    }
+}
+
+/// Turn the slice shown by the RHI view by its own width with [ and ]
+pub fn rhi_control(keycode: Res<Input<KeyCode>>, mut cut: ResMut<RhiCut>) {
+    if keycode.just_pressed(KeyCode::BracketLeft) {
+        cut.azimuth += cut.width;
+    }
+    if keycode.just_pressed(KeyCode::BracketRight) {
+        cut.azimuth -= cut.width;
+    }
+}
+
+#[derive(Component)]
+pub struct RenderModeButton;
+
+/// Move every view on to the next render mode with M or by clicking the button, and
+/// split the screen between two views with V
+pub fn render_mode_control(
+    keycode: Res<Input<KeyCode>>,
+    clicks: Query<&Interaction, (Changed<Interaction>, With<RenderModeButton>)>,
+    mut split: ResMut<SplitScreen>,
+    mut cameras: Query<(&SceneCamera, &mut RenderMode)>,
+    mut query: Query<&mut Text, With<RenderModeButton>>,
+) {
+    let clicked = clicks.iter().any(|i| *i == Interaction::Pressed);
+    let toggled = keycode.just_pressed(KeyCode::M) || clicked;
+    if toggled {
+        for (_, mut mode) in cameras.iter_mut() {
+            *mode = mode.next();
+        }
+    }
+    if keycode.just_pressed(KeyCode::V) {
+        split.0 = !split.0;
+    }
+
+    if toggled || split.is_changed() {
+        let mut cameras: Vec<_> = cameras.iter().collect();
+        cameras.sort_by_key(|(place, _)| place.0);
+        let columns = if split.0 { 2 } else { 1 };
+        let modes: Vec<_> = cameras
+            .iter()
+            .take(columns)
+            .map(|(_, mode)| format!("{:?}", **mode))
+            .collect();
+        for mut text in &mut query {
+            text.sections[0].value = format!("[{} view]", modes.join(" | "));
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::fmt;
+use std::io::BufRead;
+
+use anyhow::Result;
+
+use crate::data::{Header, Line, Step};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The step can still be shown, but something in it is ignored
+    Warning,
+    /// The step is unusable
+    Error,
+}
+
+/// A problem found in one line of a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "line {}: {}: {}", self.line, severity, self.message)
+    }
+}
+
+/// Checks the steps of a run in order, for problems that parsing alone does not catch
+pub struct Validator {
+    /// Elapsed time of the last step without errors
+    previous: Option<f64>,
+    /// Number of sensors declared in the header
+    pub sensors: usize,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Self {
+            previous: None,
+            sensors: Header::default().sensors.len(),
+        }
+    }
+}
+
+impl Validator {
+    pub fn check(&mut self, step: &Step) -> Vec<(Severity, String)> {
+        let mut problems = Vec::new();
+        let mut error = |message: String| problems.push((Severity::Error, message));
+
+        if !step.elapsed.is_finite() {
+            error(format!("elapsed time {} is not finite", step.elapsed));
+        } else if let Some(previous) = self.previous.filter(|p| step.elapsed < *p) {
+            error(format!(
+                "elapsed time {} is before the previous step's {}",
+                step.elapsed, previous
+            ));
+        }
+
+        let mut truths: Vec<_> = step.truths.iter().collect();
+        truths.sort_by(|a, b| a.0.cmp(b.0));
+        for (id, state) in truths {
+            if !all_finite(state) {
+                error(format!("truth {} has a NaN or infinite state", id));
+            }
+        }
+
+        let mut tracks: Vec<_> = step.tracks.iter().collect();
+        tracks.sort_by(|a, b| a.0.cmp(b.0));
+        for (id, track) in tracks.iter() {
+            if !all_finite(&track.state) {
+                error(format!("track {} has a NaN or infinite state", id));
+            }
+        }
+
+        let mut keys = Vec::with_capacity(step.beams.len());
+        for (position, beam) in step.beams.iter().enumerate() {
+            let key = beam.key(position);
+            if !beam.width.is_finite() || !all_finite(&beam.position) {
+                error(format!("beam {} has a NaN or infinite pointing", key));
+            }
+            let widths = [Some(beam.width), beam.az_width, beam.el_width];
+            if widths
+                .into_iter()
+                .flatten()
+                .any(|w| w <= 0.0 || !w.is_finite())
+            {
+                error(format!("beam {} has a width that is not positive", key));
+            }
+            let ranges = [beam.min_range, beam.max_range];
+            if ranges
+                .into_iter()
+                .flatten()
+                .any(|r| r < 0.0 || !r.is_finite())
+            {
+                error(format!(
+                    "beam {} has a range that is negative or not finite",
+                    key
+                ));
+            } else if let [Some(min), Some(max)] = ranges {
+                if min >= max {
+                    error(format!(
+                        "beam {} has a minimum range {} that is not below its maximum {}",
+                        key, min, max
+                    ));
+                }
+            }
+            if keys.contains(&key) {
+                error(format!("beam {} appears more than once", key));
+            }
+            if beam.sensor as usize >= self.sensors {
+                error(format!(
+                    "beam {} is from sensor {}, but the header has {} sensors",
+                    key, beam.sensor, self.sensors
+                ));
+            }
+            keys.push(key);
+        }
+
+        // The covariance is only drawn, so a bad one is not worth dropping the step over
+        for (id, track) in tracks {
+            let length = track.uncertainty.len();
+            if ![0, 21, 36].contains(&length) {
+                problems.push((
+                    Severity::Warning,
+                    format!(
+                        "track {} has {} uncertainty values, expected 21 or 36",
+                        id, length
+                    ),
+                ));
+            } else if !all_finite(&track.uncertainty) {
+                problems.push((
+                    Severity::Warning,
+                    format!("track {} has a NaN or infinite uncertainty", id),
+                ));
+            }
+        }
+
+        if problems
+            .iter()
+            .all(|(severity, _)| *severity < Severity::Error)
+        {
+            self.previous = Some(step.elapsed);
+        }
+        problems
+    }
+}
+
+fn all_finite(values: &[f32]) -> bool {
+    values.iter().all(|v| v.is_finite())
+}
+
+/// Check every line of a JSONL run, rather than stopping at the first problem
+pub fn validate(reader: impl BufRead) -> Result<Vec<Diagnostic>> {
+    let mut validator = Validator::default();
+    let mut diagnostics = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let number = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let problems = match Line::parse(&line) {
+            Ok(Line::Header(header)) if number == 1 => {
+                validator.sensors = header.sensors.len();
+                continue;
+            }
+            Ok(Line::Header(_)) => {
+                vec![(Severity::Error, "header must be the first line".to_string())]
+            }
+            Ok(Line::Step(step)) => validator.check(&step),
+            Err(e) => vec![(Severity::Error, format!("{:#}", e))],
+        };
+        diagnostics.extend(problems.into_iter().map(|(severity, message)| Diagnostic {
+            line: number,
+            severity,
+            message,
+        }));
+    }
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::data::SimulationRun;
+
+    use super::{validate, Diagnostic, Severity};
+
+    #[test]
+    fn diagnostics() {
+        let run = [
+            r#"{"elapsed": 1.0, "truths": {"a": [0, 0, 0, 0, 0, 0]}, "tracks": {}, "beams": []}"#,
+            r#"{"elapsed": 0.5, "truths": {}, "tracks": {}, "beams": []}"#,
+            r#"{"elapsed": 2.0, "truths": {"a": [0, 0, 0, 0, 0]}, "tracks": {}, "beams": []}"#,
+            r#"{"elapsed": 2.0, "truths": {}, "beams": []}"#,
+            r#"{"elapsed": 2.5, "truths": {}, "tracks": {"1": {"state": [0, 0, 0, 0, 0, 0], "uncertainty": [1]}}, "beams": []}"#,
+            r#"{"elapsed": 3.0, "truths": {"a": [0, 0, 0, 0, 0, 0]}, "tracks": {}, "beams": [{"width": 0.1, "position": [0.2, 0.3]}]}"#,
+        ]
+        .join("\n")
+        // JSON has no NaN, so the only way in is a value too large for an f32
+        .replace("0.3]", "1e39]");
+
+        let diagnostics = validate(Cursor::new(&run)).unwrap();
+        let found: Vec<_> = diagnostics.iter().map(|d| (d.line, d.severity)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (2, Severity::Error),
+                (3, Severity::Error),
+                (4, Severity::Error),
+                (5, Severity::Warning),
+                (6, Severity::Error),
+            ]
+        );
+        assert!(diagnostics[2].message.contains("missing field `tracks`"));
+
+        // Only steps with errors are left out
+        assert!(SimulationRun::from_jsonl(Cursor::new(&run), false).is_err());
+        let loaded = SimulationRun::from_jsonl(Cursor::new(&run), true).unwrap();
+        assert_eq!(loaded.elapsed, vec![1.0, 2.5]);
+    }
+
+    /// Diagnostics for a single step with the given beams
+    fn check(beams: &str) -> Vec<Diagnostic> {
+        let step = format!(
+            r#"{{"elapsed": 0.0, "truths": {{}}, "tracks": {{}}, "beams": {}}}"#,
+            beams
+        );
+        validate(Cursor::new(step)).unwrap()
+    }
+
+    #[test]
+    fn beam_ids() {
+        // The beam with id 1 is not the second beam without an id
+        assert!(check(
+            r#"[{"id": 1, "width": 0.1, "position": [0, 0]}, {"width": 0.1, "position": [0, 0]}]"#
+        )
+        .is_empty());
+        let diagnostics = check(
+            r#"[{"id": 1, "width": 0.1, "position": [0, 0]}, {"id": 1, "width": 0.1, "position": [0, 0]}]"#,
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0]
+            .message
+            .contains("beam 1 appears more than once"));
+    }
+
+    #[test]
+    fn sensors() {
+        let step = r#"{"elapsed": 0.0, "truths": {}, "tracks": {}, "beams": [{"width": 0.1, "position": [0, 0], "sensor": 1}]}"#;
+        let with_header =
+            |header: &str| validate(Cursor::new(format!("{}\n{}", header, step))).unwrap();
+
+        assert!(with_header(r#"{"sensors": [{}, {}]}"#).is_empty());
+        let diagnostics = with_header(r#"{"sensors": [{}]}"#);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+        assert!(diagnostics[0].message.contains("from sensor 1"));
+
+        // A header without sensors is rejected everywhere it could be read
+        let diagnostics = with_header(r#"{"sensors": []}"#);
+        assert_eq!(diagnostics[0].line, 1);
+        assert!(diagnostics[0].message.contains("invalid header"));
+        assert!(SimulationRun::from_jsonl(Cursor::new(r#"{"sensors": []}"#), false).is_err());
+    }
+
+    #[test]
+    fn ranges() {
+        let beam = |ranges: &str| {
+            check(&format!(
+                r#"[{{"width": 0.1, "position": [0, 0], {}}}]"#,
+                ranges
+            ))
+        };
+
+        assert!(beam(r#""min_range": 100, "max_range": 900"#).is_empty());
+        assert!(beam(r#""max_range": 900"#).is_empty());
+        for ranges in [
+            r#""min_range": -100, "max_range": 900"#,
+            r#""max_range": 1e39"#,
+            r#""min_range": 900, "max_range": 900"#,
+            r#""min_range": 1000, "max_range": 900"#,
+        ] {
+            let diagnostics = beam(ranges);
+            assert_eq!(diagnostics.len(), 1, "{}", ranges);
+            assert_eq!(diagnostics[0].severity, Severity::Error);
+        }
+    }
+}